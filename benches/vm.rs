@@ -0,0 +1,67 @@
+//! Compares the stack and register virtual machines on the same programs.
+//!
+//! Programs are compiled without optimization, as constant folding would reduce each of them to a
+//! single number. Each measurement includes cloning the compiled program, since both machines take
+//! ownership of the code they run.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use calculator::{codegen::CodeGenerator, parser::Parser, register, vm::Vm};
+
+/// How long to spend measuring each machine on each program.
+const TARGET_DURATION: Duration = Duration::from_millis(500);
+
+/// The stack machine addresses constants with a single byte, so no program may contain more than
+/// 256 numbers.
+fn programs() -> Vec<(&'static str, String)> {
+    let sum = (1..=250)
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(" + ");
+
+    let nested = (1..=200).fold("0".to_owned(), |expr, n| {
+        format!("{} {} ({})", n, ["+", "-", "*", "/", "%"][n % 5], expr)
+    });
+
+    let statements = (1..=50)
+        .map(|n| format!("-{} * 2.5 - {} / 3 % 7;", n, n))
+        .collect::<String>();
+
+    vec![
+        ("sum", format!("{};", sum)),
+        ("nested", format!("{};", nested)),
+        ("statements", statements),
+    ]
+}
+
+/// Runs `f` repeatedly for about `TARGET_DURATION`, returning the mean time per run.
+fn measure<T>(mut f: impl FnMut() -> T) -> Duration {
+    let mut iterations = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < TARGET_DURATION {
+        black_box(f());
+        iterations += 1;
+    }
+
+    start.elapsed() / iterations
+}
+
+fn main() {
+    println!("{:<12} {:>12} {:>12}", "program", "stack", "register");
+    for (name, source) in programs() {
+        let ast = Parser::new(&source).parse();
+        assert!(ast.complete(), "benchmark '{}' failed to parse", name);
+
+        let bytecode = CodeGenerator::default().generate(&ast).unwrap();
+        let program = register::codegen::CodeGenerator::default()
+            .generate(&ast)
+            .unwrap();
+
+        let stack = measure(|| Vm::new(bytecode.clone()).run().unwrap());
+        let register = measure(|| register::vm::Vm::new(program.clone()).run());
+        println!("{:<12} {:>12?} {:>12?}", name, stack, register);
+    }
+}
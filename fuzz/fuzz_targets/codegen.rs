@@ -0,0 +1,39 @@
+#![no_main]
+
+use calculator::{
+    codegen::CodeGenerator,
+    interpreter::interpret,
+    optimizer::{fold::fold_constants, peephole},
+    parser::Parser,
+    verifier::verify,
+    vm::Vm,
+};
+use libfuzzer_sys::fuzz_target;
+
+// Compiles the source with and without optimizations. Code is only generated for programs that
+// parse, and must then verify and produce the same values as the tree interpreter.
+fuzz_target!(|source: &str| {
+    let ast = Parser::new(source).parse();
+    if !ast.complete() {
+        return;
+    }
+
+    let expected = interpret(&ast);
+    let unoptimized = CodeGenerator::default().generate(&ast);
+    let optimized = CodeGenerator::default()
+        .generate(&fold_constants(&ast))
+        .map(|bytecode| peephole::optimize(&bytecode));
+    for bytecode in [unoptimized, optimized] {
+        // Only programs with too many constants can't be compiled.
+        let Ok(bytecode) = bytecode else {
+            continue;
+        };
+
+        assert!(verify(&bytecode).is_ok());
+        let values = Vm::new(bytecode).run().unwrap();
+        assert_eq!(values.len(), expected.len());
+        for (value, expected) in values.iter().zip(&expected) {
+            assert!(value.to_bits() == expected.to_bits() || value.is_nan() && expected.is_nan());
+        }
+    }
+});
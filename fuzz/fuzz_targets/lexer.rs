@@ -0,0 +1,17 @@
+#![no_main]
+
+use calculator::lexer::{token::TokenKind, Lexer};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let mut lexer = Lexer::new(source);
+    // Every call consumes at least one character or yields eof, so this always terminates.
+    for _ in 0..=source.len() {
+        if let Ok(token) = lexer.next_token() {
+            assert!(token.span.end() <= source.len());
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+        }
+    }
+});
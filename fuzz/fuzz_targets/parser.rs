@@ -0,0 +1,13 @@
+#![no_main]
+
+use calculator::{ast::Ast, parser::Parser, syntax::lower};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let mut parser = Parser::new(source);
+    let root = parser.parse_syntax();
+    assert_eq!(root.to_string(), source);
+
+    let ast = Ast::new(lower(&root), parser.errors().is_empty());
+    assert_eq!(ast.complete(), ast.decls().len() == root.nodes().count());
+});
@@ -0,0 +1,40 @@
+#![no_main]
+
+use calculator::{
+    bytecode::Bytecode,
+    lexer::span::Span,
+    verifier::{max_stack_depth, verify},
+    vm::Vm,
+};
+use libfuzzer_sys::fuzz_target;
+
+// The first byte is the number of constants, which are read eight bytes at a time, and the second
+// is the declared maximum stack depth, or zero to declare the depth the code actually reaches. The
+// rest of the input is the code. The bytecode is deliberately not verified before running it.
+fuzz_target!(|data: &[u8]| {
+    let [count, depth, rest @ ..] = data else {
+        return;
+    };
+
+    let mut bytecode = Bytecode::default();
+    for chunk in rest.chunks_exact(8).take(*count as usize) {
+        bytecode.add_constant(f64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    let code = &rest[bytecode.constants().len() * 8..];
+    for (offset, &byte) in code.iter().enumerate() {
+        bytecode.write_byte(byte, Span::new(offset, offset + 1));
+    }
+    let depth = match depth {
+        0 => max_stack_depth(&bytecode).unwrap_or(0),
+        depth => *depth as usize,
+    };
+    bytecode.set_max_stack_depth(depth);
+
+    let verified = verify(&bytecode).is_ok();
+    let _ = bytecode.to_string();
+    // The code may reach a depth beyond the default stack limit, which verified code is allowed.
+    let result = Vm::new(bytecode).with_stack_limit(depth).run();
+    if verified {
+        assert!(result.is_ok());
+    }
+});
@@ -0,0 +1,169 @@
+use std::fmt::Display;
+
+use crate::{
+    bytecode::{Bytecode, Opcode},
+    lexer::span::Span,
+};
+
+/// Assembles the textual format produced by the disassembler into `Bytecode`.
+///
+/// Each line holds at most one instruction: an optional byte offset (which is ignored), then an
+/// opcode name and its operand. There are no jumps yet, so there is nothing for a `label:` to
+/// name, and labels are rejected. Operands are constants, given either as a number literal, which
+/// is appended to the constant table, or as `#n` to refer to the constant at index `n` without
+/// adding one. The `.byte` directive writes a single raw byte, so malformed
+/// code can be written deliberately. Everything following a `;` is a comment.
+///
+/// The span of every byte written is the span of the line it was assembled from. The maximum stack
+/// depth is computed from the stack effects of the instructions as written, with any underflow
+/// left for the verifier to report.
+pub fn assemble(source: &str) -> Result<Bytecode, AssemblyError> {
+    let mut bytecode = Bytecode::default();
+    let mut depth: usize = 0;
+    let mut line_start = 0;
+    for (idx, line) in source.split_inclusive('\n').enumerate() {
+        let span = Span::new(line_start, line_start + line.trim_end().len());
+        line_start += line.len();
+
+        let error = |message: String| AssemblyError {
+            line: idx + 1,
+            span,
+            message,
+        };
+
+        let code = line.split(';').next().unwrap_or_default();
+        let mut words = code.split_whitespace().peekable();
+        words.next_if(|word| word.bytes().all(|b| b.is_ascii_digit()));
+        if let Some(label) = words.next_if(|word| word.ends_with(':')) {
+            return Err(error(format!(
+                "labels are not supported, found '{}'",
+                label
+            )));
+        }
+
+        let Some(mnemonic) = words.next() else {
+            continue;
+        };
+        let operand = words.next();
+        if let Some(word) = words.next() {
+            return Err(error(format!("unexpected '{}'", word)));
+        }
+
+        if mnemonic == ".byte" {
+            let operand = operand.ok_or_else(|| error("'.byte' requires a value".to_owned()))?;
+            let byte = operand
+                .strip_prefix("0x")
+                .map_or_else(|| operand.parse(), |hex| u8::from_str_radix(hex, 16))
+                .map_err(|_| error(format!("invalid byte '{}'", operand)))?;
+            bytecode.write_byte(byte, span);
+            continue;
+        }
+
+        let opcode: Opcode = mnemonic
+            .parse()
+            .map_err(|_| error(format!("unknown opcode '{}'", mnemonic)))?;
+        let (pops, pushes) = opcode.stack_effect();
+        depth = depth.saturating_sub(pops) + pushes;
+        if depth > bytecode.max_stack_depth() {
+            bytecode.set_max_stack_depth(depth);
+        }
+
+        match (opcode.operand_len(), operand) {
+            (1, Some(operand)) => {
+                let idx = match operand.strip_prefix('#') {
+                    Some(idx) => idx
+                        .parse()
+                        .map_err(|_| error(format!("invalid constant index '{}'", operand)))?,
+                    None => {
+                        let value = operand
+                            .parse()
+                            .map_err(|_| error(format!("invalid number '{}'", operand)))?;
+                        bytecode
+                            .add_constant(value)
+                            .try_into()
+                            .map_err(|_| error("too many constants".to_owned()))?
+                    }
+                };
+                bytecode.write_opcode(opcode, span);
+                bytecode.write_byte(idx, span);
+            }
+            (1, None) => {
+                return Err(error(format!("'{:?}' requires an operand", opcode)));
+            }
+            (_, Some(operand)) => {
+                return Err(error(format!(
+                    "'{:?}' takes no operand, got '{}'",
+                    opcode, operand
+                )));
+            }
+            (_, None) => bytecode.write_opcode(opcode, span),
+        }
+    }
+
+    Ok(bytecode)
+}
+
+#[derive(Debug, Clone)]
+pub struct AssemblyError {
+    /// The line number the error occurred on.
+    pub line: usize,
+    /// The span of the line the error occurred on.
+    pub span: Span,
+    pub message: String,
+}
+
+impl Display for AssemblyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{codegen::CodeGenerator, parser::Parser, vm::Vm};
+
+    use super::{assemble, AssemblyError};
+
+    #[test]
+    fn round_trip() -> Result<(), AssemblyError> {
+        let ast = Parser::new("-(1 + 2) * 3.5;\n4 % 0;").parse();
+        let bytecode = CodeGenerator::default().generate(&ast).unwrap();
+        let assembled = assemble(&bytecode.to_string())?;
+        assert_eq!(assembled.as_ref(), bytecode.as_ref());
+        assert_eq!(assembled.constants(), bytecode.constants());
+
+        Ok(())
+    }
+
+    #[test]
+    fn program() -> Result<(), AssemblyError> {
+        let bytecode = assemble(
+            "; (2 - 0.5) * 2, then 2 again
+                Constant 2
+                Constant 0.5
+                Subtract
+                Constant #0
+                Multiply
+                Emit
+                Constant #0 ; reuses the first constant
+                Emit
+            ",
+        )?;
+        assert_eq!(Vm::new(bytecode).run().unwrap(), vec![3.0, 2.0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn error_line() {
+        let err = assemble("Constant 1\nEmit\nConstant\n").unwrap_err();
+        assert_eq!(err.line, 3);
+
+        let err = assemble("Constant 1\nFrobnicate\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.message, "unknown opcode 'Frobnicate'");
+
+        let err = assemble("start: Constant 1\n").unwrap_err();
+        assert_eq!(err.message, "labels are not supported, found 'start:'");
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::Write;
+
 use crate::lexer::span::Span;
 
 #[derive(Debug, Clone, Copy)]
@@ -31,6 +33,21 @@ impl Ast {
     pub fn complete(&self) -> bool {
         self.complete
     }
+
+    /// Returns an indented tree of every node in the `Ast` along with its span.
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        for decl in &self.decls {
+            decl.dump(&mut out, 0);
+        }
+
+        out
+    }
+}
+
+fn dump_node(out: &mut String, depth: usize, label: std::fmt::Arguments, span: Span) {
+    writeln!(out, "{:indent$}{} {}", "", label, span, indent = depth * 2)
+        .expect("writing to a string cannot fail");
 }
 
 #[derive(Debug, Clone)]
@@ -53,6 +70,15 @@ impl Decl {
         let span = stmt.span();
         Self(Spanned::new(DeclKind::Stmt(Box::new(stmt)), span))
     }
+
+    fn dump(&self, out: &mut String, depth: usize) {
+        match self.kind() {
+            DeclKind::Stmt(stmt) => {
+                dump_node(out, depth, format_args!("Decl::Stmt"), self.span());
+                stmt.dump(out, depth + 1);
+            }
+        }
+    }
 }
 
 impl From<Spanned<DeclKind>> for Decl {
@@ -93,6 +119,15 @@ impl Stmt {
         let span = expr.span();
         Self(Spanned::new(StmtKind::Expr(Box::new(expr)), span))
     }
+
+    fn dump(&self, out: &mut String, depth: usize) {
+        match self.kind() {
+            StmtKind::Expr(expr) => {
+                dump_node(out, depth, format_args!("Stmt::Expr"), self.span());
+                expr.dump(out, depth + 1);
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -123,12 +158,49 @@ impl Expr {
         )
     }
 
+    /// Whether this or any expression within it failed to parse.
+    pub fn has_error(&self) -> bool {
+        match self.kind() {
+            ExprKind::Number(_) => false,
+            ExprKind::Unary(_, operand) => operand.has_error(),
+            ExprKind::Binary(_, operand_1, operand_2) => {
+                operand_1.has_error() || operand_2.has_error()
+            }
+            ExprKind::Error => true,
+        }
+    }
+
     pub fn binary(operator: BinOp, operand_1: Expr, operand_2: Expr) -> Self {
         Self::new(
             Span::between(operand_1.span(), operand_2.span()),
             ExprKind::Binary(operator, Box::new(operand_1), Box::new(operand_2)),
         )
     }
+
+    fn dump(&self, out: &mut String, depth: usize) {
+        match self.kind() {
+            ExprKind::Number(value) => {
+                dump_node(
+                    out,
+                    depth,
+                    format_args!("Expr::Number({})", value),
+                    self.span(),
+                );
+            }
+            ExprKind::Unary(op, operand) => {
+                let label = format_args!("Expr::Unary({:?})", op.kind());
+                dump_node(out, depth, label, self.span());
+                operand.dump(out, depth + 1);
+            }
+            ExprKind::Binary(op, operand_1, operand_2) => {
+                let label = format_args!("Expr::Binary({:?})", op.kind());
+                dump_node(out, depth, label, self.span());
+                operand_1.dump(out, depth + 1);
+                operand_2.dump(out, depth + 1);
+            }
+            ExprKind::Error => dump_node(out, depth, format_args!("Expr::Error"), self.span()),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -136,6 +208,9 @@ pub enum ExprKind {
     Number(f64),
     Unary(UnOp, Box<Expr>),
     Binary(BinOp, Box<Expr>, Box<Expr>),
+    /// An expression that failed to parse, kept so that the rest of its statement can still be
+    /// analyzed. Only an `Ast` that is not complete contains one.
+    Error,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -160,6 +235,15 @@ pub enum UnOpKind {
     Neg,
 }
 
+impl UnOpKind {
+    /// Applies the operator with the same semantics as the `Vm`.
+    pub fn evaluate(self, operand: f64) -> f64 {
+        match self {
+            UnOpKind::Neg => -operand,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct BinOp(Spanned<BinOpKind>);
 
@@ -185,3 +269,16 @@ pub enum BinOpKind {
     Div,
     Rem,
 }
+
+impl BinOpKind {
+    /// Applies the operator with the same semantics as the `Vm`.
+    pub fn evaluate(self, operand_1: f64, operand_2: f64) -> f64 {
+        match self {
+            BinOpKind::Add => operand_1 + operand_2,
+            BinOpKind::Sub => operand_1 - operand_2,
+            BinOpKind::Mul => operand_1 * operand_2,
+            BinOpKind::Div => operand_1 / operand_2,
+            BinOpKind::Rem => operand_1 % operand_2,
+        }
+    }
+}
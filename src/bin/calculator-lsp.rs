@@ -0,0 +1,32 @@
+use std::process::ExitCode;
+
+use calculator::lsp::{read_message, write_message, Server};
+
+fn main() -> ExitCode {
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+    let mut server = Server::default();
+
+    loop {
+        let body = match read_message(&mut stdin) {
+            Ok(Some(body)) => body,
+            // The client went away without asking the server to exit.
+            Ok(None) => return ExitCode::FAILURE,
+            Err(err) => {
+                eprintln!("error: could not read message: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        for message in server.handle_message(&body) {
+            if let Err(err) = write_message(&mut stdout, &message) {
+                eprintln!("error: could not write message: {}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+
+        if let Some(code) = server.exit_code() {
+            return ExitCode::from(code);
+        }
+    }
+}
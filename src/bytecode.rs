@@ -1,3 +1,14 @@
+mod serialize;
+
+pub use self::serialize::{DeserializeError, FORMAT_VERSION};
+
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use crate::{disassembler::Disassembler, lexer::span::Span};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Opcode {
     Constant,
@@ -9,6 +20,11 @@ pub enum Opcode {
     Divide,
     Remainder,
     Negate,
+    Emit,
+    AddConstant,
+    SubtractConstant,
+    MultiplyConstant,
+    DivideConstant,
 }
 
 impl TryFrom<u8> for Opcode {
@@ -25,6 +41,38 @@ impl TryFrom<u8> for Opcode {
             6 => Self::Divide,
             7 => Self::Remainder,
             8 => Self::Negate,
+            9 => Self::Emit,
+            10 => Self::AddConstant,
+            11 => Self::SubtractConstant,
+            12 => Self::MultiplyConstant,
+            13 => Self::DivideConstant,
+            _ => return Err(()),
+        };
+
+        Ok(opcode)
+    }
+}
+
+impl FromStr for Opcode {
+    type Err = ();
+
+    /// Parses the name of an opcode as it appears in disassembly.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let opcode = match s {
+            "Constant" => Self::Constant,
+            "Pop" => Self::Pop,
+            "Return" => Self::Return,
+            "Add" => Self::Add,
+            "Subtract" => Self::Subtract,
+            "Multiply" => Self::Multiply,
+            "Divide" => Self::Divide,
+            "Remainder" => Self::Remainder,
+            "Negate" => Self::Negate,
+            "Emit" => Self::Emit,
+            "AddConstant" => Self::AddConstant,
+            "SubtractConstant" => Self::SubtractConstant,
+            "MultiplyConstant" => Self::MultiplyConstant,
+            "DivideConstant" => Self::DivideConstant,
             _ => return Err(()),
         };
 
@@ -32,19 +80,80 @@ impl TryFrom<u8> for Opcode {
     }
 }
 
+impl Opcode {
+    /// The number of operand bytes that follow the opcode. The only kind of operand is the index
+    /// of a constant.
+    pub fn operand_len(self) -> usize {
+        match self {
+            Opcode::Constant
+            | Opcode::AddConstant
+            | Opcode::SubtractConstant
+            | Opcode::MultiplyConstant
+            | Opcode::DivideConstant => 1,
+            _ => 0,
+        }
+    }
+
+    /// The number of values the instruction pops from the stack, and the number it then pushes.
+    pub fn stack_effect(self) -> (usize, usize) {
+        match self {
+            Opcode::Constant => (0, 1),
+            Opcode::Pop | Opcode::Emit => (1, 0),
+            Opcode::Return => (0, 0),
+            Opcode::Negate
+            | Opcode::AddConstant
+            | Opcode::SubtractConstant
+            | Opcode::MultiplyConstant
+            | Opcode::DivideConstant => (1, 1),
+            Opcode::Add
+            | Opcode::Subtract
+            | Opcode::Multiply
+            | Opcode::Divide
+            | Opcode::Remainder => (2, 1),
+        }
+    }
+}
+
+/// A single decoded instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub operand: Option<u8>,
+}
+
+impl Instruction {
+    /// The number of bytes the instruction occupies, including the opcode.
+    pub fn size(&self) -> usize {
+        1 + self.opcode.operand_len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The byte does not correspond to any `Opcode`.
+    InvalidOpcode(u8),
+    /// The code ended before all of the instruction's operands.
+    UnexpectedEnd,
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Bytecode {
     code: Vec<u8>,
+    /// The span of the source code each byte of `code` was generated from.
+    spans: Vec<Span>,
     constants: Vec<f64>,
+    /// The greatest number of values on the stack at any point while running `code`.
+    max_stack_depth: usize,
 }
 
 impl Bytecode {
-    pub fn write_byte(&mut self, byte: u8) {
+    pub fn write_byte(&mut self, byte: u8, span: Span) {
         self.code.push(byte);
+        self.spans.push(span);
     }
 
-    pub fn write_opcode(&mut self, opcode: Opcode) {
-        self.code.push(opcode as u8);
+    pub fn write_opcode(&mut self, opcode: Opcode, span: Span) {
+        self.write_byte(opcode as u8, span);
     }
 
     pub fn add_constant(&mut self, value: f64) -> usize {
@@ -56,28 +165,50 @@ impl Bytecode {
     pub fn constant<T: Into<usize>>(&self, idx: T) -> f64 {
         self.constants[idx.into()]
     }
+
+    pub fn constants(&self) -> &[f64] {
+        &self.constants
+    }
+
+    pub fn max_stack_depth(&self) -> usize {
+        self.max_stack_depth
+    }
+
+    pub fn set_max_stack_depth(&mut self, depth: usize) {
+        self.max_stack_depth = depth;
+    }
+
+    /// Discards the spans of the code, for when the source it was generated from is unavailable.
+    pub fn strip_spans(&mut self) {
+        self.spans.clear();
+    }
+
+    /// Returns the span of the source code the byte at `offset` was generated from, if known.
+    pub fn span(&self, offset: usize) -> Option<Span> {
+        self.spans.get(offset).copied()
+    }
+
+    /// Decodes the instruction beginning at `offset`.
+    pub fn decode(&self, offset: usize) -> Result<Instruction, DecodeError> {
+        let byte = *self.code.get(offset).ok_or(DecodeError::UnexpectedEnd)?;
+        let opcode = Opcode::try_from(byte).map_err(|_| DecodeError::InvalidOpcode(byte))?;
+        let operand = match opcode.operand_len() {
+            0 => None,
+            _ => Some(
+                *self
+                    .code
+                    .get(offset + 1)
+                    .ok_or(DecodeError::UnexpectedEnd)?,
+            ),
+        };
+
+        Ok(Instruction { opcode, operand })
+    }
 }
 
 impl Display for Bytecode {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut it = self.code.iter();
-        while let Some(&byte) = it.next() {
-            let op: Opcode = byte.try_into().expect("invalid opcode");
-            write!(f, "{:?}", op)?;
-
-            // Print operands
-            match op {
-                Opcode::Constant => {
-                    writeln!(f, " {:02X}", it.next().expect("expected operand"))?;
-                }
-                _ => {
-                    writeln!(f)?;
-                }
-            }
-        }
-        writeln!(f, "{:?}", self.constants)?;
-
-        Ok(())
+        Disassembler::new(self).fmt(f)
     }
 }
 
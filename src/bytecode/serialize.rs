@@ -0,0 +1,275 @@
+//! The binary file format for `Bytecode`.
+//!
+//! All integers are little-endian. A file consists of:
+//!
+//! | Field          | Encoding                                                    |
+//! |----------------|-------------------------------------------------------------|
+//! | magic          | the bytes `CALB`                                            |
+//! | version        | `u16`, currently [`FORMAT_VERSION`]                         |
+//! | flags          | `u16`, bit 0 is set if the debug section is present         |
+//! | stack depth    | `u32`, the maximum stack depth of the code                  |
+//! | constant pool  | `u32` count, then per entry a `u8` tag and its payload      |
+//! | code           | `u32` length, then the code itself                          |
+//! | debug section  | one `u32` start and `u32` end per byte of code              |
+//! | checksum       | `u32` CRC-32 of every preceding byte                        |
+//!
+//! The only constant tag is `0`, a number whose payload is the 8 bytes of an IEEE 754 double.
+
+use std::fmt::Display;
+
+use super::Bytecode;
+use crate::lexer::span::Span;
+
+const MAGIC: &[u8; 4] = b"CALB";
+/// Incremented whenever the layout of the file, or the meaning of an opcode, changes.
+pub const FORMAT_VERSION: u16 = 3;
+
+const FLAG_DEBUG: u16 = 1 << 0;
+const TAG_NUMBER: u8 = 0;
+
+impl Bytecode {
+    /// Encodes the bytecode in the binary file format, including its spans if it has any.
+    pub fn serialize(&self) -> Vec<u8> {
+        let has_spans = !self.spans.is_empty();
+
+        let mut out = vec![];
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&(if has_spans { FLAG_DEBUG } else { 0 }).to_le_bytes());
+        out.extend_from_slice(&(self.max_stack_depth as u32).to_le_bytes());
+
+        out.extend_from_slice(&(self.constants.len() as u32).to_le_bytes());
+        for constant in &self.constants {
+            out.push(TAG_NUMBER);
+            out.extend_from_slice(&constant.to_le_bytes());
+        }
+
+        out.extend_from_slice(&(self.code.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.code);
+
+        if has_spans {
+            for span in &self.spans {
+                out.extend_from_slice(&(span.start() as u32).to_le_bytes());
+                out.extend_from_slice(&(span.end() as u32).to_le_bytes());
+            }
+        }
+
+        let checksum = crc32(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    /// Decodes bytecode previously encoded by [`Bytecode::serialize`].
+    pub fn deserialize(bytes: &[u8]) -> Result<Bytecode, DeserializeError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(DeserializeError::BadMagic);
+        }
+
+        let (body, checksum) = bytes
+            .split_last_chunk::<4>()
+            .ok_or(DeserializeError::UnexpectedEnd)?;
+        let mut reader = Reader {
+            bytes: body,
+            pos: MAGIC.len(),
+        };
+
+        let version = reader.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(DeserializeError::UnsupportedVersion(version));
+        }
+        if crc32(body) != u32::from_le_bytes(*checksum) {
+            return Err(DeserializeError::ChecksumMismatch);
+        }
+
+        let flags = reader.u16()?;
+        if flags & !FLAG_DEBUG != 0 {
+            return Err(DeserializeError::UnknownFlags(flags));
+        }
+        let max_stack_depth = reader.u32()? as usize;
+
+        let constant_count = reader.u32()? as usize;
+        let mut constants = vec![];
+        for _ in 0..constant_count {
+            match reader.u8()? {
+                TAG_NUMBER => constants.push(f64::from_le_bytes(reader.array()?)),
+                tag => return Err(DeserializeError::UnknownConstantTag(tag)),
+            }
+        }
+
+        let code_len = reader.u32()? as usize;
+        let code = reader.take(code_len)?.to_vec();
+
+        let mut spans = vec![];
+        if flags & FLAG_DEBUG != 0 {
+            for _ in 0..code_len {
+                let start = reader.u32()? as usize;
+                let end = reader.u32()? as usize;
+                if end < start {
+                    return Err(DeserializeError::InvalidSpan);
+                }
+                spans.push(Span::new(start, end));
+            }
+        }
+
+        if reader.pos != body.len() {
+            return Err(DeserializeError::TrailingBytes);
+        }
+
+        Ok(Bytecode {
+            code,
+            spans,
+            constants,
+            max_stack_depth,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// The file does not begin with the magic number, so is not a bytecode file at all.
+    BadMagic,
+    /// The file was written by an incompatible version of the format.
+    UnsupportedVersion(u16),
+    UnknownFlags(u16),
+    UnknownConstantTag(u8),
+    /// A span in the debug section ends before it starts.
+    InvalidSpan,
+    /// The file ended in the middle of a section.
+    UnexpectedEnd,
+    /// There is data between the last section and the checksum.
+    TrailingBytes,
+    /// The contents of the file do not match its checksum.
+    ChecksumMismatch,
+}
+
+impl DeserializeError {
+    /// The stable code identifying the error, which `calculator --explain` describes.
+    pub fn code(&self) -> &'static str {
+        "E0400"
+    }
+}
+
+impl Display for DeserializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeserializeError::BadMagic => write!(f, "not a bytecode file"),
+            DeserializeError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported format version {} (expected {})",
+                version, FORMAT_VERSION
+            ),
+            DeserializeError::UnknownFlags(flags) => write!(f, "unknown flags {:#06x}", flags),
+            DeserializeError::UnknownConstantTag(tag) => {
+                write!(f, "unknown constant tag {}", tag)
+            }
+            DeserializeError::InvalidSpan => write!(f, "invalid span in debug section"),
+            DeserializeError::UnexpectedEnd => write!(f, "file is truncated"),
+            DeserializeError::TrailingBytes => write!(f, "unexpected data after code"),
+            DeserializeError::ChecksumMismatch => write!(f, "checksum mismatch, file is corrupted"),
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DeserializeError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(DeserializeError::UnexpectedEnd)?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DeserializeError> {
+        Ok(self.take(N)?.try_into().expect("slice has length N"))
+    }
+
+    fn u8(&mut self) -> Result<u8, DeserializeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DeserializeError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, DeserializeError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+}
+
+/// CRC-32 as used by zlib and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{bytecode::Bytecode, codegen::CodeGenerator, parser::Parser};
+
+    use super::{crc32, DeserializeError, FORMAT_VERSION};
+
+    fn compile(source: &str) -> Bytecode {
+        CodeGenerator::default()
+            .generate(&Parser::new(source).parse())
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trip() -> Result<(), DeserializeError> {
+        let bytecode = compile("-(1 + 2) * 3.5; 4 % 0;");
+        let loaded = Bytecode::deserialize(&bytecode.serialize())?;
+        assert_eq!(loaded.as_ref(), bytecode.as_ref());
+        assert_eq!(loaded.constants(), bytecode.constants());
+        assert_eq!(loaded.span(0), bytecode.span(0));
+        assert_eq!(loaded.max_stack_depth(), bytecode.max_stack_depth());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let bytes = compile("1 + 2;").serialize();
+
+        let mut flipped = bytes.clone();
+        flipped[12] ^= 1;
+        assert_eq!(
+            Bytecode::deserialize(&flipped).unwrap_err(),
+            DeserializeError::ChecksumMismatch
+        );
+
+        for len in 0..bytes.len() {
+            assert!(Bytecode::deserialize(&bytes[..len]).is_err());
+        }
+
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Bytecode::deserialize(&newer).unwrap_err(),
+            DeserializeError::UnsupportedVersion(FORMAT_VERSION + 1)
+        );
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
@@ -1,21 +1,47 @@
 use crate::{
     ast::{Ast, BinOpKind, Decl, DeclKind, Expr, ExprKind, Stmt, StmtKind, UnOpKind},
     bytecode::{Bytecode, Opcode},
+    diagnostics::{Diagnostic, Label},
+    lexer::span::Span,
+    register::Register,
 };
 
 #[derive(Debug, Default)]
 pub struct CodeGenerator {
     bytecode: Bytecode,
-    had_error: bool,
+    /// The depth of the stack after running the code generated so far.
+    depth: usize,
+    /// The first error found, after which code is still generated but then discarded.
+    error: Option<CodegenError>,
 }
 
 impl CodeGenerator {
-    pub fn generate(&mut self, ast: &Ast) -> Bytecode {
+    pub fn generate(&mut self, ast: &Ast) -> Result<Bytecode, CodegenError> {
         for decl in ast.decls() {
             self.decl(decl);
         }
 
-        std::mem::take(&mut self.bytecode)
+        self.depth = 0;
+        let bytecode = std::mem::take(&mut self.bytecode);
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(bytecode),
+        }
+    }
+
+    fn error(&mut self, err: CodegenError) {
+        self.error.get_or_insert(err);
+    }
+
+    fn write_opcode(&mut self, opcode: Opcode, span: Span) {
+        let (pops, pushes) = opcode.stack_effect();
+        // The generated code is balanced even after an error, but the depth must never wrap.
+        self.depth = self.depth.saturating_sub(pops) + pushes;
+        if self.depth > self.bytecode.max_stack_depth() {
+            self.bytecode.set_max_stack_depth(self.depth);
+        }
+
+        self.bytecode.write_opcode(opcode, span);
     }
 
     fn decl(&mut self, decl: &Decl) {
@@ -28,7 +54,7 @@ impl CodeGenerator {
         match stmt.kind() {
             StmtKind::Expr(expr) => {
                 self.expr(expr);
-                self.bytecode.write_byte(Opcode::Pop as u8);
+                self.write_opcode(Opcode::Emit, stmt.span());
             }
         }
     }
@@ -37,30 +63,128 @@ impl CodeGenerator {
         match expr.kind() {
             ExprKind::Number(value) => {
                 let idx = self.bytecode.add_constant(*value);
-                if let Ok(idx) = idx.try_into() {
-                    self.bytecode.write_opcode(Opcode::Constant);
-                    self.bytecode.write_byte(idx);
-                } else {
-                    self.had_error = true;
-                }
+                let idx = idx.try_into().unwrap_or_else(|_| {
+                    self.error(CodegenError::TooManyConstants { span: expr.span() });
+                    u8::MAX
+                });
+                self.write_opcode(Opcode::Constant, expr.span());
+                self.bytecode.write_byte(idx, expr.span());
+            }
+            // A constant keeps the stack balanced for the rest of the statement, so that every
+            // error is still found.
+            ExprKind::Error => {
+                self.error(CodegenError::Incomplete { span: expr.span() });
+                self.expr(&Expr::new(expr.span(), ExprKind::Number(f64::NAN)));
             }
             ExprKind::Unary(op, expr) => {
                 self.expr(expr);
-                self.bytecode.write_opcode(match op.kind() {
-                    UnOpKind::Neg => Opcode::Negate,
-                });
+                self.write_opcode(
+                    match op.kind() {
+                        UnOpKind::Neg => Opcode::Negate,
+                    },
+                    op.span(),
+                );
             }
             ExprKind::Binary(op, expr_l, expr_r) => {
                 self.expr(expr_l);
                 self.expr(expr_r);
-                self.bytecode.write_opcode(match op.kind() {
-                    BinOpKind::Add => Opcode::Add,
-                    BinOpKind::Sub => Opcode::Subtract,
-                    BinOpKind::Mul => Opcode::Multiply,
-                    BinOpKind::Div => Opcode::Divide,
-                    BinOpKind::Rem => Opcode::Remainder,
-                });
+                self.write_opcode(
+                    match op.kind() {
+                        BinOpKind::Add => Opcode::Add,
+                        BinOpKind::Sub => Opcode::Subtract,
+                        BinOpKind::Mul => Opcode::Multiply,
+                        BinOpKind::Div => Opcode::Divide,
+                        BinOpKind::Rem => Opcode::Remainder,
+                    },
+                    op.span(),
+                );
             }
         }
     }
 }
+
+/// Code could not be generated for an `Ast`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodegenError {
+    /// The program needs more constants than an instruction's one-byte operand can refer to, the
+    /// first that doesn't fit being at `span`.
+    TooManyConstants { span: Span },
+    /// The program needs more registers than an instruction can refer to, the first that doesn't
+    /// fit holding the value of the expression at `span`.
+    TooManyRegisters { span: Span },
+    /// The `Ast` has errors, the first of which is at `span`.
+    Incomplete { span: Span },
+}
+
+impl CodegenError {
+    /// The most constants a program may have.
+    pub const MAX_CONSTANTS: usize = u8::MAX as usize + 1;
+    /// The most registers a register program may use.
+    pub const MAX_REGISTERS: usize = Register::MAX as usize + 1;
+
+    pub fn message(&self) -> String {
+        match self {
+            CodegenError::TooManyConstants { .. } => {
+                format!("program has more than {} constants", Self::MAX_CONSTANTS)
+            }
+            CodegenError::TooManyRegisters { .. } => {
+                format!("program needs more than {} registers", Self::MAX_REGISTERS)
+            }
+            CodegenError::Incomplete { .. } => "program has syntax errors".to_owned(),
+        }
+    }
+
+    /// The stable code identifying the error, which `calculator --explain` describes.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CodegenError::TooManyConstants { .. } => "E0300",
+            CodegenError::Incomplete { .. } => "E0301",
+            CodegenError::TooManyRegisters { .. } => "E0302",
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            CodegenError::TooManyConstants { span }
+            | CodegenError::TooManyRegisters { span }
+            | CodegenError::Incomplete { span } => *span,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let label = match self {
+            CodegenError::TooManyConstants { .. } => "this constant is one too many",
+            CodegenError::TooManyRegisters { .. } => "this value needs one register too many",
+            CodegenError::Incomplete { .. } => "this could not be parsed",
+        };
+        Diagnostic::error(self.message())
+            .with_code(self.code())
+            .with_label(Label::primary(self.span(), label))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::Parser;
+
+    use super::{CodeGenerator, CodegenError};
+
+    #[test]
+    fn too_many_constants() {
+        let generate = |count| {
+            let source: String = (0..count).map(|i| format!("{}.5;", i)).collect();
+            CodeGenerator::default().generate(&Parser::new(&source).parse())
+        };
+
+        assert!(generate(CodegenError::MAX_CONSTANTS).is_ok());
+        let err = generate(300).unwrap_err();
+        assert_eq!(err.code(), "E0300");
+        // The 257th constant is `256.5`, after statements of four, five and six characters.
+        assert_eq!(err.span().start(), 10 * 4 + 90 * 5 + 156 * 6);
+
+        assert!(matches!(
+            CodeGenerator::default().generate(&Parser::new("1 + ;").parse()),
+            Err(CodegenError::Incomplete { .. })
+        ));
+    }
+}
@@ -1,26 +1,864 @@
-use crate::lexer::span::Span;
-
-pub fn report_error(message: &str, span: Span, source: &str) {
-    let line = span.starting_line_number(source);
-    let column = span.starting_column_number(source);
-    let margin_width = 3 + digit_count(line as u32) + digit_count(column as u32);
-
-    eprintln!("error: {message}");
-    if !span.is_empty() {
-        let src_line = source.lines().nth(line - 1).expect("invalid line");
-        eprintln!("[{}:{}] {}", line, column, src_line);
-        eprintln!(
-            "{} {}",
-            str::repeat(" ", margin_width as usize + (column - 1)),
-            str::repeat("^", span.len()),
-        );
-    } else {
-        // The only kind of token that has an empty span is eof which is always the last token
-        // yielded by the lexer.
-        eprintln!("[eof]");
+//! Diagnostics, and rendering them against the source in the style of rustc.
+//!
+//! ```text
+//! error[E0101]: expected ')', found ';'
+//!  --> 1:7
+//!   |
+//! 1 | (1 + 2;
+//!   | -     ^ expected ')'
+//!   | |
+//!   | opening parenthesis here
+//! ```
+
+mod explain;
+
+pub use self::explain::explain;
+
+use std::{
+    fmt::Write,
+    io::{IsTerminal, Write as _},
+};
+
+use crate::{json::Value, lexer::span::Span};
+
+/// The number of columns a tab is rendered as.
+const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+/// A span of the source to point out, with a message about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    /// Whether this is where the problem is, rather than context for it.
+    pub primary: bool,
+}
+
+impl Label {
+    pub fn primary(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+            primary: true,
+        }
+    }
+
+    pub fn secondary(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+            primary: false,
+        }
+    }
+}
+
+/// A message printed after the source, such as `= help: ...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Footer {
+    Help(String),
+    Note(String),
+}
+
+/// An edit to the source that would fix the problem: replacing `span` with `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    severity: Severity,
+    code: Option<&'static str>,
+    message: String,
+    labels: Vec<Label>,
+    footers: Vec<Footer>,
+    suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            labels: vec![],
+            footers: vec![],
+            suggestions: vec![],
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_label(mut self, label: Label) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.footers.push(Footer::Help(help.into()));
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.footers.push(Footer::Note(note.into()));
+        self
+    }
+
+    pub fn with_suggestion(
+        mut self,
+        span: Span,
+        replacement: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        self.suggestions.push(Suggestion {
+            span,
+            replacement: replacement.into(),
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn code(&self) -> Option<&'static str> {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    pub fn footers(&self) -> &[Footer] {
+        &self.footers
+    }
+
+    pub fn suggestions(&self) -> &[Suggestion] {
+        &self.suggestions
+    }
+
+    /// The label pointing at the problem, or failing that the first label.
+    pub fn primary_label(&self) -> Option<&Label> {
+        self.labels
+            .iter()
+            .find(|label| label.primary)
+            .or(self.labels.first())
+    }
+
+    /// Renders the diagnostic with every line of `source` that its labels point into, colored
+    /// with ANSI escape codes if `color` is set.
+    pub fn render(&self, source: &str, color: bool) -> String {
+        let style = Style(color);
+        let lines = Lines::new(source);
+        let mut out = String::new();
+
+        let severity = self.severity.as_str();
+        let header = match self.code {
+            Some(code) => format!("{}[{}]", severity, code),
+            None => severity.to_owned(),
+        };
+        writeln!(
+            out,
+            "{}{}",
+            style.paint(&header, self.severity.color()),
+            style.paint(&format!(": {}", self.message), BOLD)
+        )
+        .expect("writing to a string cannot fail");
+
+        let segments = self.segments(&lines);
+        let suggested_lines = self
+            .suggestions
+            .iter()
+            .map(|suggestion| lines.line_of(suggestion.span.start()));
+        let gutter = segments
+            .iter()
+            .map(|segment| segment.line)
+            .chain(suggested_lines)
+            .map(|line| digit_count(line + 1))
+            .max()
+            .unwrap_or(0);
+
+        if let Some(label) = self.primary_label() {
+            let (line, column) = lines.location(source, label.span.start());
+            let arrow = style.paint("-->", BLUE);
+            writeln!(out, "{:gutter$}{} {}:{}", "", arrow, line + 1, column + 1)
+                .expect("writing to a string cannot fail");
+            self.write_gutter(&mut out, style, gutter, "");
+        }
+
+        let mut previous = None;
+        let mut i = 0;
+        while i < segments.len() {
+            let line = segments[i].line;
+            let count = segments[i..].iter().take_while(|s| s.line == line).count();
+            if previous.is_some_and(|previous| line > previous + 1) {
+                writeln!(out, "{}", style.paint("...", BLUE))
+                    .expect("writing to a string cannot fail");
+            }
+            self.write_line(
+                &mut out,
+                style,
+                gutter,
+                source,
+                &lines,
+                &segments[i..i + count],
+            );
+            previous = Some(line);
+            i += count;
+        }
+
+        if !segments.is_empty() && !self.footers.is_empty() {
+            self.write_gutter(&mut out, style, gutter, "");
+        }
+        for footer in &self.footers {
+            let (kind, message) = match footer {
+                Footer::Help(message) => ("help", message),
+                Footer::Note(message) => ("note", message),
+            };
+            writeln!(
+                out,
+                "{:gutter$} {} {}: {}",
+                "",
+                style.paint("=", BLUE),
+                style.paint(kind, BOLD),
+                message
+            )
+            .expect("writing to a string cannot fail");
+        }
+
+        for suggestion in &self.suggestions {
+            self.write_suggestion(&mut out, style, gutter, source, &lines, suggestion);
+        }
+
+        out
+    }
+
+    /// Returns the diagnostic as a JSON object, with the span of its primary label, every label,
+    /// footer and suggestion. Spans are given both as byte offsets and as one-based lines and
+    /// columns in characters.
+    pub fn to_json(&self, source: &str) -> Value {
+        let lines = Lines::new(source);
+        let span = |span: Span| {
+            let (line_start, column_start) = lines.location(source, span.start());
+            let (line_end, column_end) = lines.location(source, span.end());
+            Value::object([
+                ("byte_start", span.start().into()),
+                ("byte_end", span.end().into()),
+                ("line_start", (line_start + 1).into()),
+                ("column_start", (column_start + 1).into()),
+                ("line_end", (line_end + 1).into()),
+                ("column_end", (column_end + 1).into()),
+            ])
+        };
+
+        let labels: Vec<_> = self
+            .labels
+            .iter()
+            .map(|label| {
+                Value::object([
+                    ("span", span(label.span)),
+                    ("message", label.message.as_str().into()),
+                    ("primary", label.primary.into()),
+                ])
+            })
+            .collect();
+        let footers = |help: bool| -> Vec<_> {
+            self.footers
+                .iter()
+                .filter_map(|footer| match footer {
+                    Footer::Help(message) if help => Some(message.as_str()),
+                    Footer::Note(message) if !help => Some(message.as_str()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let suggestions: Vec<_> = self
+            .suggestions
+            .iter()
+            .map(|suggestion| {
+                Value::object([
+                    ("span", span(suggestion.span)),
+                    ("replacement", suggestion.replacement.as_str().into()),
+                    ("message", suggestion.message.as_str().into()),
+                ])
+            })
+            .collect();
+
+        Value::object([
+            ("severity", self.severity.as_str().into()),
+            ("code", self.code.into()),
+            ("message", self.message.as_str().into()),
+            (
+                "span",
+                self.primary_label().map(|label| span(label.span)).into(),
+            ),
+            ("labels", labels.into()),
+            ("help", footers(true).into()),
+            ("notes", footers(false).into()),
+            ("suggestions", suggestions.into()),
+        ])
+    }
+
+    /// Splits the labels into the parts underlining each line, in order of line and column.
+    fn segments<'a>(&'a self, lines: &Lines) -> Vec<Segment<'a>> {
+        let mut segments = vec![];
+        for label in &self.labels {
+            let span = label.span;
+            let first = lines.line_of(span.start());
+            let last = lines.line_of(span.end().saturating_sub(1).max(span.start()));
+            for line in first..=last {
+                let start = if line == first {
+                    span.start()
+                } else {
+                    lines.start(line)
+                };
+                let end = if line == last {
+                    span.end().max(start)
+                } else {
+                    lines.end(line)
+                };
+                segments.push(Segment {
+                    line,
+                    start,
+                    end,
+                    primary: label.primary,
+                    message: (line == last && !label.message.is_empty())
+                        .then_some(label.message.as_str()),
+                });
+            }
+        }
+        segments.sort_by_key(|segment| (segment.line, segment.start));
+
+        segments
+    }
+
+    fn write_line(
+        &self,
+        out: &mut String,
+        style: Style,
+        gutter: usize,
+        source: &str,
+        lines: &Lines,
+        segments: &[Segment],
+    ) {
+        let line = segments[0].line;
+        let text = lines.text(line, source);
+        let line_start = lines.start(line);
+        let column =
+            |offset: usize| width(&text[..offset.min(line_start + text.len()) - line_start]);
+
+        let number = style.paint(&format!("{:>gutter$} |", line + 1), BLUE);
+        let text = expand_tabs(text);
+        if text.trim_end().is_empty() {
+            writeln!(out, "{}", number)
+        } else {
+            writeln!(out, "{} {}", number, text.trim_end())
+        }
+        .expect("writing to a string cannot fail");
+
+        // Underline every segment, with the message of the last one written after it.
+        let mut row = Row::default();
+        for segment in segments {
+            let start = column(segment.start);
+            let len = (column(segment.end) - start).max(1);
+            let (marker, color) = if segment.primary {
+                ('^', self.severity.color())
+            } else {
+                ('-', BLUE)
+            };
+            row.put(start, &marker.to_string().repeat(len), color);
+        }
+        let (last, rest) = segments.split_last().expect("segments is not empty");
+        if let Some(message) = last.message {
+            let end = row.len() + 1;
+            let color = if last.primary {
+                self.severity.color()
+            } else {
+                BLUE
+            };
+            row.put(end, message, color);
+        }
+        self.write_gutter(out, style, gutter, &row.render(style));
+
+        // The messages of the others are hung below, from right to left.
+        let labelled: Vec<_> = rest
+            .iter()
+            .filter_map(|segment| Some((column(segment.start), segment.message?, segment.primary)))
+            .collect();
+        for i in (0..labelled.len()).rev() {
+            let mut connectors = Row::default();
+            for (start, _, _) in &labelled[..=i] {
+                connectors.put(*start, "|", BLUE);
+            }
+            self.write_gutter(out, style, gutter, &connectors.render(style));
+
+            let mut row = Row::default();
+            for (start, _, _) in &labelled[..i] {
+                row.put(*start, "|", BLUE);
+            }
+            let (start, message, primary) = labelled[i];
+            row.put(
+                start,
+                message,
+                if primary { self.severity.color() } else { BLUE },
+            );
+            self.write_gutter(out, style, gutter, &row.render(style));
+        }
+    }
+
+    /// Writes the message of `suggestion`, followed by the line it changes as it would be after
+    /// the change, with the inserted or replacing text marked.
+    fn write_suggestion(
+        &self,
+        out: &mut String,
+        style: Style,
+        gutter: usize,
+        source: &str,
+        lines: &Lines,
+        suggestion: &Suggestion,
+    ) {
+        writeln!(out, "{}: {}", style.paint("help", BOLD), suggestion.message)
+            .expect("writing to a string cannot fail");
+
+        let line = lines.line_of(suggestion.span.start());
+        let text = lines.text(line, source);
+        let start = suggestion.span.start() - lines.start(line);
+        let end = suggestion.span.end().saturating_sub(lines.start(line));
+        let (Some(before), Some(after)) = (text.get(..start), text.get(end..)) else {
+            // The suggestion spans several lines, so there is no one line to show.
+            return;
+        };
+        if suggestion.replacement.contains('\n') || start > end {
+            return;
+        }
+
+        // A removal is shown on the line as it is, and otherwise the line is shown fixed.
+        let removed = &text[start..end];
+        let (shown, marker, marked) = if suggestion.replacement.is_empty() {
+            (text.to_owned(), "-", removed)
+        } else {
+            let fixed = format!("{}{}{}", before, suggestion.replacement, after);
+            let marker = if removed.is_empty() { "+" } else { "~" };
+            (fixed, marker, suggestion.replacement.as_str())
+        };
+        self.write_gutter(out, style, gutter, "");
+        let number = style.paint(&format!("{:>gutter$} |", line + 1), BLUE);
+        writeln!(out, "{} {}", number, expand_tabs(&shown).trim_end())
+            .expect("writing to a string cannot fail");
+
+        let mut row = Row::default();
+        row.put(width(before), &marker.repeat(width(marked).max(1)), GREEN);
+        self.write_gutter(out, style, gutter, &row.render(style));
     }
+
+    fn write_gutter(&self, out: &mut String, style: Style, gutter: usize, text: &str) {
+        let bar = style.paint(&format!("{:gutter$} |", ""), BLUE);
+        if text.is_empty() {
+            writeln!(out, "{}", bar)
+        } else {
+            writeln!(out, "{} {}", bar, text)
+        }
+        .expect("writing to a string cannot fail");
+    }
+}
+
+/// Applies every suggestion that does not overlap one before it, returning the changed source and
+/// the number of suggestions applied.
+pub fn apply_suggestions(source: &str, suggestions: &[Suggestion]) -> (String, usize) {
+    let mut suggestions: Vec<_> = suggestions.iter().collect();
+    suggestions.sort_by_key(|suggestion| suggestion.span.start());
+
+    let mut out = String::with_capacity(source.len());
+    let mut end = 0;
+    let mut applied = 0;
+    for suggestion in suggestions {
+        let span = suggestion.span;
+        let Some(unchanged) = source.get(end..span.start()) else {
+            continue;
+        };
+        if span.end() < span.start() || source.get(span.start()..span.end()).is_none() {
+            continue;
+        }
+
+        out.push_str(unchanged);
+        out.push_str(&suggestion.replacement);
+        end = span.end();
+        applied += 1;
+    }
+    out.push_str(&source[end..]);
+
+    (out, applied)
+}
+
+/// Returns the candidate closest to `name` by edit distance, if any is close enough for `name` to
+/// likely be a misspelling of it.
+pub fn closest_name<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The fewest characters inserted, removed or replaced to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // The distances from a prefix of `a` to every prefix of `b`.
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(a != *b))
+                .min(above + 1)
+                .min(row[j] + 1);
+            diagonal = above;
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Prints `diagnostic` to stderr, in color if stderr is a terminal and `NO_COLOR` is not set.
+pub fn report(diagnostic: &Diagnostic, source: &str) {
+    let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let _ = std::io::stderr().write_all(diagnostic.render(source, color).as_bytes());
+}
+
+/// The part of a label on a single line.
+#[derive(Debug)]
+struct Segment<'a> {
+    line: usize,
+    /// Byte offsets of the underlined part of the line.
+    start: usize,
+    end: usize,
+    primary: bool,
+    /// The label's message, on the last line it covers.
+    message: Option<&'a str>,
+}
+
+/// The offsets at which the lines of the source start.
+struct Lines(Vec<usize>);
+
+impl Lines {
+    fn new(source: &str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        starts.push(source.len() + 1);
+        Self(starts)
+    }
+
+    /// The line containing `offset`, clamped to the last line.
+    fn line_of(&self, offset: usize) -> usize {
+        let line = self.0.partition_point(|start| *start <= offset) - 1;
+        line.min(self.0.len() - 2)
+    }
+
+    fn start(&self, line: usize) -> usize {
+        self.0[line]
+    }
+
+    /// The end of the text of `line`, before its newline.
+    fn end(&self, line: usize) -> usize {
+        self.0[line + 1] - 1
+    }
+
+    fn text<'a>(&self, line: usize, source: &'a str) -> &'a str {
+        &source[self.start(line)..self.end(line)]
+    }
+
+    /// The zero-based line and column in characters of `offset`, which is clamped to the source
+    /// and rounded down to a character boundary.
+    fn location(&self, source: &str, offset: usize) -> (usize, usize) {
+        let mut offset = offset.min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_of(offset);
+        (line, source[self.start(line)..offset].chars().count())
+    }
+}
+
+/// A row of text placed at display columns, each piece with a color.
+#[derive(Default)]
+struct Row(Vec<Option<(char, &'static str)>>);
+
+impl Row {
+    fn put(&mut self, column: usize, text: &str, color: &'static str) {
+        for (i, c) in text.chars().enumerate() {
+            if self.0.len() <= column + i {
+                self.0.resize(column + i + 1, None);
+            }
+            self.0[column + i] = Some((c, color));
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn render(&self, style: Style) -> String {
+        let mut out = String::new();
+        let mut i = 0;
+        while i < self.0.len() {
+            match self.0[i] {
+                None => {
+                    out.push(' ');
+                    i += 1;
+                }
+                Some((_, color)) => {
+                    let run: String = self.0[i..]
+                        .iter()
+                        .map_while(|cell| cell.filter(|(_, c)| *c == color).map(|(c, _)| c))
+                        .collect();
+                    i += run.chars().count();
+                    out.push_str(&style.paint(&run, color));
+                }
+            }
+        }
+
+        out
+    }
+}
+
+const BOLD: &str = "1";
+const RED: &str = "1;31";
+const YELLOW: &str = "1;33";
+const BLUE: &str = "1;34";
+const GREEN: &str = "1;32";
+
+#[derive(Clone, Copy)]
+struct Style(bool);
+
+impl Style {
+    fn paint(self, text: &str, code: &str) -> String {
+        if self.0 {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_owned()
+        }
+    }
+}
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+/// The number of columns `text` takes up in a terminal.
+fn width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
 }
 
-fn digit_count(n: u32) -> u32 {
-    n.ilog10() + 1
+/// The number of columns `c` takes up in a terminal: none for combining marks and other zero
+/// width characters, and two for wide east Asian characters and emoji.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x09 => TAB_WIDTH,
+        0x00..=0x1F | 0x7F..=0x9F => 0,
+        0x0300..=0x036F | 0x200B..=0x200F | 0x20D0..=0x20FF | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+fn digit_count(n: usize) -> usize {
+    n.checked_ilog10().unwrap_or(0) as usize + 1
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lexer::span::Span;
+
+    use super::{apply_suggestions, closest_name, digit_count, edit_distance, Diagnostic, Label};
+
+    #[test]
+    fn names() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("sqr", "sqrt"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+
+        let builtins = ["sqrt", "sin", "abs"];
+        assert_eq!(closest_name("sqr", &builtins), Some("sqrt"));
+        assert_eq!(closest_name("sine", &builtins), Some("sin"));
+        assert_eq!(closest_name("cos", &builtins), None);
+        assert_eq!(closest_name("x", &[]), None);
+    }
+
+    #[test]
+    fn digit_count_of_zero() {
+        assert_eq!(digit_count(0), 1);
+        assert_eq!(digit_count(9), 1);
+        assert_eq!(digit_count(10), 2);
+    }
+
+    #[test]
+    fn labels_and_footers() {
+        let diagnostic = Diagnostic::error("expected ')', found ';'")
+            .with_code("E0101")
+            .with_label(Label::primary(Span::new(6, 7), "expected ')'"))
+            .with_label(Label::secondary(
+                Span::new(0, 1),
+                "opening parenthesis here",
+            ))
+            .with_help("close the parenthesis");
+        assert_eq!(
+            diagnostic.render("(1 + 2;", false),
+            "\
+error[E0101]: expected ')', found ';'
+ --> 1:7
+  |
+1 | (1 + 2;
+  | -     ^ expected ')'
+  | |
+  | opening parenthesis here
+  |
+  = help: close the parenthesis
+"
+        );
+    }
+
+    #[test]
+    fn multiple_lines() {
+        let source = "1;\n\n\n\n\n\n\n\n(2\n+ 3;\n";
+        let diagnostic = Diagnostic::error("mismatched")
+            .with_label(Label::primary(Span::new(11, 16), "these"))
+            .with_label(Label::secondary(Span::new(0, 1), "first"))
+            .with_label(Label::primary(Span::new(source.len(), source.len()), "end"));
+        assert_eq!(
+            diagnostic.render(source, false),
+            "\
+error: mismatched
+  --> 9:2
+   |
+ 1 | 1;
+   | - first
+...
+ 9 | (2
+   |  ^
+10 | + 3;
+   | ^^^ these
+11 |
+   | ^ end
+"
+        );
+    }
+
+    #[test]
+    fn tabs_and_wide_characters() {
+        let source = "\t好 # 1;";
+        let diagnostic = Diagnostic::error("unexpected character")
+            .with_label(Label::primary(Span::new(5, 6), ""));
+        assert_eq!(
+            diagnostic.render(source, false),
+            "error: unexpected character\n --> 1:4\n  |\n1 |     好 # 1;\n  |        ^\n"
+        );
+
+        let colored = diagnostic.render(source, true);
+        assert!(colored.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: unexpected character\x1b[0m\n"));
+    }
+
+    #[test]
+    fn json() {
+        let source = "1;\né(2;";
+        let diagnostic = Diagnostic::error("expected ')', found <eof>")
+            .with_code("E0101")
+            .with_label(Label::primary(Span::new(9, 9), "expected ')'"))
+            .with_label(Label::secondary(
+                Span::new(5, 6),
+                "opening parenthesis here",
+            ))
+            .with_note("a note")
+            .with_suggestion(Span::new(9, 9), ")", "close the parenthesis");
+        let json = diagnostic.to_json(source);
+
+        assert_eq!(json["code"].as_str(), Some("E0101"));
+        assert_eq!(json["span"]["byte_start"].as_usize(), Some(9));
+        assert_eq!(json["span"]["line_start"].as_usize(), Some(2));
+        assert_eq!(json["span"]["column_start"].as_usize(), Some(5));
+        assert_eq!(
+            json["labels"][1]["span"]["column_start"].as_usize(),
+            Some(2)
+        );
+        assert_eq!(json["labels"][1]["primary"], false.into());
+        assert_eq!(json["notes"][0].as_str(), Some("a note"));
+        assert_eq!(json["help"].as_array().map(<[_]>::len), Some(0));
+        assert_eq!(json["suggestions"][0]["replacement"].as_str(), Some(")"));
+    }
+
+    #[test]
+    fn suggestions() {
+        let source = "1;\n(2 x 3;";
+        let diagnostic = Diagnostic::error("expected ')', found ';'")
+            .with_label(Label::primary(Span::new(9, 10), "expected ')'"))
+            .with_suggestion(Span::new(9, 9), ")", "close the parenthesis");
+        assert_eq!(
+            diagnostic.render(source, false),
+            "\
+error: expected ')', found ';'
+ --> 2:7
+  |
+2 | (2 x 3;
+  |       ^ expected ')'
+help: close the parenthesis
+  |
+2 | (2 x 3);
+  |       +
+"
+        );
+
+        let diagnostic = diagnostic.with_suggestion(Span::new(6, 7), "*", "multiply");
+        let overlapping = diagnostic.with_suggestion(Span::new(6, 8), "", "remove");
+        assert_eq!(
+            apply_suggestions(source, overlapping.suggestions()),
+            ("1;\n(2 * 3);".to_owned(), 2)
+        );
+    }
 }
@@ -0,0 +1,315 @@
+//! Long-form explanations of error codes, printed by `calculator --explain`.
+
+use crate::{codegen::CodegenError, parser::Parser, vm::Vm};
+
+/// Returns the explanation of `code`, such as `E0100`, or `None` if there is no such code.
+pub fn explain(code: &str) -> Option<String> {
+    let text = match code {
+        "E0001" => {
+            "\
+A character in the source does not begin any token.
+
+Programs are made up of numbers, the operators `+`, `-`, `*`, `/` and `%`, parentheses, and the
+semicolons that separate statements. Any other character outside of a comment is an error.
+
+Erroneous code example:
+
+    1 + 2 # add;
+
+Comments begin with `//` and run to the end of the line:
+
+    1 + 2; // add
+"
+        }
+        "E0002" => {
+            "\
+A string was not closed before the end of the source.
+
+The language has no strings yet, so this error is reserved and not currently reported.
+"
+        }
+        "E0100" => {
+            "\
+An expression was expected, but another token was found.
+
+Expressions are numbers, parenthesized expressions, negations, and two expressions joined by a
+binary operator. This error is reported when one is missing, such as when an operator has no
+operand on its right or a statement is empty.
+
+Erroneous code example:
+
+    1 + ;
+    ;
+
+Complete the expression, or remove the stray semicolon:
+
+    1 + 2;
+"
+        }
+        "E0101" => {
+            "\
+A particular token was expected, but another was found.
+
+Every opening parenthesis must be closed, and statements must be separated by semicolons. Where a
+semicolon may be left out depends on where the source was read from:
+
+- An expression given on the command line may leave out the semicolon after its last statement.
+- A script read from a `.calc` file or from stdin may also end a statement at the end of a line,
+  except inside parentheses. A line starting with an operator begins a new statement rather than
+  continuing the one before it.
+- Programs embedding the parser require every statement to end with a semicolon, unless they
+  choose one of these modes with `Parser::with_mode`.
+
+Erroneous code example:
+
+    1 + 2 3
+    (3 * 4
+
+Add the missing tokens:
+
+    1 + 2; 3
+    (3 * 4)
+"
+        }
+        "E0102" => {
+            return Some(format!(
+                "\
+An expression is nested more deeply than the parser allows.
+
+Parsing nested expressions uses the stack, so to avoid running out of it expressions may be
+nested at most {} deep. Each parenthesis, negation and operand on the right of an operator
+counts as a level.
+
+Erroneous code example:
+
+    ((((((((((((((((((((((((((((((((...1...))))))))))))))))))))))))))))))));
+
+Remove redundant parentheses and negations, since `((1))` is `1` and `--1` is `1`.
+",
+                Parser::MAX_DEPTH
+            ))
+        }
+        "E0103" => {
+            "\
+A name was used that the language does not define.
+
+The language has no variables, functions or built-ins yet, so every name is unknown. A misspelled
+built-in would be suggested, once there are some. A lone `x` between two numbers is taken to be a
+mistyped `*`.
+
+Erroneous code example:
+
+    sqrt(4);
+    2 x 3;
+
+Write the expression with operators instead:
+
+    2 * 3;
+"
+        }
+        "E0200" => {
+            "\
+An instruction popped a value from an empty stack.
+
+The compiler never generates such bytecode, so it was written by hand or corrupted. `calculator
+run` verifies bytecode before running it, and rejects files containing it.
+"
+        }
+        "E0201" => {
+            "\
+The bytecode contains a byte that is not an opcode where an instruction was expected.
+
+The compiler never generates such bytecode, so it was written by hand, corrupted, or compiled by
+an incompatible version. Recompile the program from its source with `calculator compile`.
+"
+        }
+        "E0202" => {
+            "\
+The bytecode ended in the middle of an instruction, before all of its operands.
+
+The compiler never generates such bytecode, so it was truncated or written by hand. Recompile the
+program from its source with `calculator compile`.
+"
+        }
+        "E0203" => {
+            "\
+An instruction referred to a constant that is not in the bytecode's constant table.
+
+The compiler never generates such bytecode, so it was written by hand or corrupted. Recompile the
+program from its source with `calculator compile`.
+"
+        }
+        "E0204" => {
+            return Some(format!(
+                "\
+The program pushed more values onto the stack than it may hold.
+
+The stack holds up to {} values unless a lower limit was set with `Vm::with_stack_limit`.
+Expressions nest too shallowly for compiled programs to reach the default limit, so this only
+happens with a lower limit or with bytecode written by hand.
+",
+                Vm::DEFAULT_STACK_LIMIT
+            ))
+        }
+        "E0205" => {
+            "\
+The program ran out of fuel or passed its deadline.
+
+Programs embedding the virtual machine can limit the number of instructions it executes with
+`Vm::with_fuel`, and the time it runs for with `Vm::with_deadline`. Raise the limits, or split
+the program into smaller ones.
+"
+        }
+        "E0206" => {
+            "\
+The program would have held more memory than its limit.
+
+Programs embedding the virtual machine can limit the memory it holds with
+`Vm::with_memory_limit`. Raise the limit, or simplify the expression being evaluated so that fewer
+values are held on the stack at once.
+"
+        }
+        "E0300" => {
+            return Some(format!(
+                "\
+The program has more constants than can be compiled to bytecode.
+
+Instructions refer to constants by a one-byte index, so a program may have at most {} of them.
+Every number in the source is a constant, except where `-O1` folds an operation on constants into
+a single one.
+
+Split the program into smaller ones, or run it with `--vm register` or `--vm tree`, which have no
+such limit.
+",
+                CodegenError::MAX_CONSTANTS
+            ))
+        }
+        "E0301" => {
+            "\
+Code was generated for a program with syntax errors.
+
+`calculator` never compiles a program that failed to parse, so this is only reported to programs
+embedding the compiler that pass it an incomplete `Ast`. Check `Ast::complete` first.
+"
+        }
+        "E0302" => {
+            return Some(format!(
+                "\
+The program needs more registers than register code can refer to.
+
+Register instructions name their operands with a two-byte index, so a program may use at most {}
+registers at once. Each level of nesting in an expression holds one more register, but programs
+that parse nest too shallowly to reach this, so it is only reported to programs embedding the
+compiler that build a deeper `Ast` themselves.
+
+Split the expression into several statements, or run it with `--vm stack` or `--vm tree`.
+",
+                CodegenError::MAX_REGISTERS
+            ))
+        }
+        "E0400" => {
+            "\
+A bytecode file could not be loaded.
+
+The file is not a bytecode file, was truncated or corrupted, or was written by an incompatible
+version of `calculator`. Recompile the program from its source with `calculator compile`.
+"
+        }
+        "E0401" => {
+            "\
+A bytecode file was loaded, but its code failed verification.
+
+Before running bytecode, `calculator run` checks that every instruction is valid, that every
+constant it refers to exists, and that the stack never holds fewer values than an instruction pops
+or more than the file declares. The compiler never generates code failing these checks, so the
+file was written by hand or corrupted. Recompile the program from its source with
+`calculator compile`.
+"
+        }
+        _ => return None,
+    };
+
+    Some(text.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        bytecode::DeserializeError,
+        codegen::CodegenError,
+        lexer::{span::Span, token::TokenKind, LexicalErrorKind},
+        parser::SyntacticErrorKind,
+        verifier::{VerifyError, VerifyErrorKind},
+        vm::VmError,
+    };
+
+    use super::explain;
+
+    #[test]
+    fn every_code_is_explained() {
+        let lexical = [
+            LexicalErrorKind::Unexpected,
+            LexicalErrorKind::UnterminatedString,
+        ]
+        .map(|kind| kind.code());
+        let syntactic = [
+            SyntacticErrorKind::ExpectedExpression {
+                found: TokenKind::Eof,
+            },
+            SyntacticErrorKind::Expected {
+                expected: TokenKind::Semicolon,
+                found: TokenKind::Eof,
+                previous: Span::new(0, 1),
+                opening: None,
+            },
+            SyntacticErrorKind::TooDeep,
+            SyntacticErrorKind::UnknownName,
+        ]
+        .map(|kind| kind.code());
+        let runtime = [
+            VmError::MissingOperand,
+            VmError::InvalidOpcode,
+            VmError::UnexpectedEnd,
+            VmError::ConstantOutOfRange(0),
+            VmError::StackOverflow,
+            VmError::BudgetExhausted { span: None },
+            VmError::OutOfMemory {
+                span: Some(Span::new(0, 1)),
+            },
+        ]
+        .map(|err| err.code());
+        let compile = [
+            CodegenError::TooManyConstants {
+                span: Span::new(0, 1),
+            },
+            CodegenError::Incomplete {
+                span: Span::new(0, 1),
+            },
+            CodegenError::TooManyRegisters {
+                span: Span::new(0, 1),
+            },
+        ]
+        .map(|err| err.code());
+        let bytecode = [
+            DeserializeError::BadMagic.code(),
+            VerifyError {
+                offset: 0,
+                kind: VerifyErrorKind::StackUnderflow,
+            }
+            .code(),
+        ];
+
+        let codes: Vec<_> = lexical
+            .iter()
+            .chain(&syntactic)
+            .chain(&runtime)
+            .chain(&compile)
+            .chain(&bytecode)
+            .collect();
+        for (i, code) in codes.iter().enumerate() {
+            assert!(explain(code).is_some(), "{} is not explained", code);
+            assert!(!codes[..i].contains(code), "{} is used twice", code);
+        }
+        assert_eq!(explain("E9999"), None);
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt::{Display, Formatter, Write};
+
+use crate::bytecode::Bytecode;
+
+/// Renders `Bytecode` as one line per instruction: its byte offset, the opcode, its decoded
+/// operand, and a comment recording where in the source it came from.
+///
+/// Constant operands are written as the value they refer to, so the listing can be read back by
+/// the assembler. Bytes that do not decode to a complete instruction are written as `.byte`
+/// directives rather than rejected.
+#[derive(Debug, Clone, Copy)]
+pub struct Disassembler<'a> {
+    bytecode: &'a Bytecode,
+    source: Option<&'a str>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(bytecode: &'a Bytecode) -> Self {
+        Self {
+            bytecode,
+            source: None,
+        }
+    }
+
+    /// Annotates instructions with line and column numbers instead of byte spans, and precedes
+    /// each run of instructions generated from the same line with that line's text.
+    pub fn with_source(mut self, source: &'a str) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    fn write_line(
+        &self,
+        f: &mut Formatter<'_>,
+        offset: usize,
+        mnemonic: &str,
+        operand: &str,
+        comment: &str,
+    ) -> std::fmt::Result {
+        let mut line = format!("{:04}  {:<16} {:<12}", offset, mnemonic, operand);
+        if !comment.is_empty() {
+            write!(line, " ; {}", comment)?;
+        }
+
+        writeln!(f, "{}", line.trim_end())
+    }
+
+    /// Describes the origin of the byte at `offset`, writing a header for its source line to `f`
+    /// if it differs from `current_line`.
+    fn location(
+        &self,
+        f: &mut Formatter<'_>,
+        offset: usize,
+        current_line: &mut Option<usize>,
+    ) -> Result<String, std::fmt::Error> {
+        let Some(span) = self.bytecode.span(offset) else {
+            return Ok(String::new());
+        };
+
+        match self.source {
+            Some(source) if source.get(span.start()..span.end()).is_some() => {
+                let line = span.starting_line_number(source);
+                if *current_line != Some(line) {
+                    let text = source.lines().nth(line - 1).unwrap_or_default();
+                    writeln!(f, "; {} | {}", line, text.trim_end())?;
+                    *current_line = Some(line);
+                }
+
+                Ok(format!("{}:{}", line, span.starting_column_number(source)))
+            }
+            _ => Ok(span.to_string()),
+        }
+    }
+}
+
+impl Display for Disassembler<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let code = self.bytecode.as_ref();
+        let mut current_line = None;
+        let mut offset = 0;
+        while offset < code.len() {
+            let location = self.location(f, offset, &mut current_line)?;
+            let Ok(instruction) = self.bytecode.decode(offset) else {
+                let operand = format!("0x{:02X}", code[offset]);
+                self.write_line(f, offset, ".byte", &operand, &location)?;
+                offset += 1;
+                continue;
+            };
+
+            let mnemonic = format!("{:?}", instruction.opcode);
+            match instruction.operand {
+                Some(idx) => match self.bytecode.constants().get(idx as usize) {
+                    Some(value) => {
+                        let comment = format!("#{} {}", idx, location);
+                        self.write_line(f, offset, &mnemonic, &value.to_string(), &comment)?;
+                    }
+                    None => {
+                        let comment = format!("no such constant {}", location);
+                        self.write_line(f, offset, &mnemonic, &format!("#{}", idx), &comment)?;
+                    }
+                },
+                None => self.write_line(f, offset, &mnemonic, "", &location)?,
+            }
+
+            offset += instruction.size();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        bytecode::{Bytecode, Opcode},
+        lexer::span::Span,
+    };
+
+    use super::Disassembler;
+
+    #[test]
+    fn invalid_bytes_as_data() {
+        let span = Span::new(0, 3);
+        let mut bytecode = Bytecode::default();
+        bytecode.add_constant(2.5);
+        bytecode.write_opcode(Opcode::Constant, span);
+        bytecode.write_byte(0, span);
+        bytecode.write_opcode(Opcode::Emit, span);
+        bytecode.write_byte(0xFF, span);
+        bytecode.write_opcode(Opcode::Constant, span);
+
+        assert_eq!(
+            Disassembler::new(&bytecode).with_source("2.5;").to_string(),
+            "; 1 | 2.5;\n\
+             0000  Constant         2.5          ; #0 1:1\n\
+             0002  Emit                          ; 1:1\n\
+             0003  .byte            0xFF         ; 1:1\n\
+             0004  .byte            0x00         ; 1:1\n",
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! Formats source code canonically, for `calculator fmt`.
+//!
+//! Statements are printed from the `Ast`, one per line and each ending with `;` whether or not it
+//! was written with one, with the same spacing and minimal parentheses as the printer. Statements
+//! too long for a line are broken before the operators of their outermost chain of equal
+//! precedence. Comments are kept: a comment following a statement on the same line stays there,
+//! and every other comment is put on its own line before the next statement, including comments
+//! written inside a statement. Runs of blank lines between statements and comments are collapsed
+//! to one.
+
+use crate::{
+    ast::{Ast, BinOp, DeclKind, Expr, ExprKind, StmtKind},
+    lexer::{span::Span, token::TokenKind, Lexer},
+    parser::infix_binding_power,
+    printer::{binop_str, needs_parens, print_expr, unop_str, Position},
+};
+
+/// The number of columns beyond which statements are broken over several lines.
+pub const MAX_WIDTH: usize = 80;
+
+/// How far continuation lines are indented from the start of the expression they continue.
+const INDENT: usize = 4;
+
+/// Formats `ast`, which must have been parsed without errors from `source`.
+pub fn format(ast: &Ast, source: &str) -> String {
+    let (statements, comments) = scan(ast, source);
+    let mut comments = comments.iter().peekable();
+    let mut out = Output::new(source);
+
+    for (decl, statement) in ast.decls().iter().zip(statements) {
+        let expr = match decl.kind() {
+            DeclKind::Stmt(stmt) => match stmt.kind() {
+                StmtKind::Expr(expr) => expr,
+            },
+        };
+
+        while let Some(comment) = comments.next_if(|comment| comment.start() < statement.end()) {
+            out.line(*comment, comment.slice(source).trim_end());
+        }
+
+        let mut text = format_expr(expr, 0, 1);
+        text.push(';');
+        let mut span = statement;
+        let trailing =
+            comments.next_if(|comment| !source[statement.end()..comment.start()].contains('\n'));
+        if let Some(comment) = trailing {
+            text.push(' ');
+            text.push_str(comment.slice(source).trim_end());
+            span = Span::between(statement, *comment);
+        }
+        out.line(span, &text);
+    }
+
+    for comment in comments {
+        out.line(*comment, comment.slice(source).trim_end());
+    }
+
+    out.text
+}
+
+/// Returns the span of each statement in `ast`, from its first token to its semicolon if it has
+/// one, and of each comment.
+fn scan(ast: &Ast, source: &str) -> (Vec<Span>, Vec<Span>) {
+    let mut lexer = Lexer::new(source);
+    let mut tokens = vec![];
+    loop {
+        match lexer.next_token() {
+            Ok(token) if token.kind == TokenKind::Eof => break,
+            Ok(token) => tokens.push(token),
+            Err(_) => {}
+        }
+    }
+
+    // The span of an expression leaves out the parentheses around it, so the statement continues
+    // through any closing parentheses after it.
+    let mut tokens = tokens.into_iter().peekable();
+    let statements = ast
+        .decls()
+        .iter()
+        .map_while(|decl| {
+            let first = tokens.next()?.span;
+            let mut last = first;
+            while let Some(token) = tokens.next_if(|token| token.span.end() <= decl.span().end()) {
+                last = token.span;
+            }
+            while let Some(token) = tokens.next_if(|token| token.kind == TokenKind::RParen) {
+                last = token.span;
+            }
+            if let Some(token) = tokens.next_if(|token| token.kind == TokenKind::Semicolon) {
+                last = token.span;
+            }
+            Some(Span::between(first, last))
+        })
+        .collect();
+
+    (statements, lexer.comments().to_vec())
+}
+
+/// Formatted lines, and the end of the source they were formatted from so far.
+struct Output<'a> {
+    source: &'a str,
+    text: String,
+    end: Option<usize>,
+}
+
+impl<'a> Output<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            text: String::new(),
+            end: None,
+        }
+    }
+
+    /// Writes `line` formatted from `span`, after a blank line if there was one before `span`.
+    fn line(&mut self, span: Span, line: &str) {
+        if let Some(end) = self.end.filter(|end| *end <= span.start()) {
+            if self.source[end..span.start()].matches('\n').count() > 1 {
+                self.text.push('\n');
+            }
+        }
+        self.text.push_str(line);
+        self.text.push('\n');
+        self.end = Some(span.end());
+    }
+}
+
+/// Formats `expr` starting at `column`, with `trailing` more characters to follow on its last line.
+fn format_expr(expr: &Expr, column: usize, trailing: usize) -> String {
+    let flat = print_expr(expr);
+    if column + flat.len() + trailing <= MAX_WIDTH {
+        return flat;
+    }
+
+    match expr.kind() {
+        ExprKind::Number(_) | ExprKind::Error => flat,
+        ExprKind::Unary(op, operand) => {
+            let prefix = unop_str(op);
+            let operand = format_operand(
+                operand,
+                Position::Operand(op),
+                column + prefix.len(),
+                trailing,
+            );
+            format!("{}{}", prefix, operand)
+        }
+        ExprKind::Binary(..) => {
+            let (first, rest) = chain(expr);
+            let indent = column + INDENT;
+            let mut out = format_operand(first, Position::Left(rest[0].0), column, 0);
+            for (i, (op, operand)) in rest.iter().enumerate() {
+                let trailing = if i + 1 == rest.len() { trailing } else { 0 };
+                let prefix = format!("{} ", binop_str(op));
+                out.push('\n');
+                out.push_str(&" ".repeat(indent));
+                out.push_str(&prefix);
+                out.push_str(&format_operand(
+                    operand,
+                    Position::Right(op),
+                    indent + prefix.len(),
+                    trailing,
+                ));
+            }
+            out
+        }
+    }
+}
+
+fn format_operand(expr: &Expr, position: Position, column: usize, trailing: usize) -> String {
+    if needs_parens(expr, position) {
+        format!("({})", format_expr(expr, column + 1, trailing + 1))
+    } else {
+        format_expr(expr, column, trailing)
+    }
+}
+
+/// Splits a binary expression into its leftmost operand and the operators and right operands of
+/// the chain of operators with the same precedence, such as `a`, `+ b`, `- c` for `a + b - c`.
+fn chain(expr: &Expr) -> (&Expr, Vec<(&BinOp, &Expr)>) {
+    let mut first = expr;
+    let mut rest = vec![];
+    let mut binding_power = None;
+    while let ExprKind::Binary(op, operand_1, operand_2) = first.kind() {
+        if *binding_power.get_or_insert(infix_binding_power(op)) != infix_binding_power(op) {
+            break;
+        }
+        rest.push((op, &**operand_2));
+        first = operand_1;
+    }
+    rest.reverse();
+
+    (first, rest)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::{Mode, Parser};
+
+    use super::format;
+
+    fn fmt(source: &str) -> String {
+        let ast = Parser::new(source).parse();
+        assert!(ast.complete());
+        format(&ast, source)
+    }
+
+    #[test]
+    fn spacing_and_parentheses() {
+        assert_eq!(
+            fmt("1+2*3;((4*5))-(6-7);"),
+            "1 + 2 * 3;\n4 * 5 - (6 - 7);\n"
+        );
+        assert_eq!(fmt("-(1+2)%-3 ;"), "-(1 + 2) % -3;\n");
+    }
+
+    #[test]
+    fn optional_semicolons() {
+        let source = "((1 + 2)) // sum\n-(3)\n\n(4 *\n 5);";
+        let ast = Parser::new(source).with_mode(Mode::Lines).parse();
+        assert!(ast.complete());
+        assert_eq!(format(&ast, source), "1 + 2; // sum\n-3;\n\n4 * 5;\n");
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let source = "// total\n1+2; // trailing\n\n\n3 // inside\n*4;\n// end\n";
+        assert_eq!(
+            fmt(source),
+            "// total\n1 + 2; // trailing\n\n// inside\n3 * 4;\n// end\n"
+        );
+        assert_eq!(fmt(&fmt(source)), fmt(source));
+    }
+
+    #[test]
+    fn wrapping() {
+        let term = "1234567890 * 1234567890";
+        let source = format!("{0} + {0} - ({0} + {0} + {0});", term);
+        assert_eq!(
+            fmt(&source),
+            format!(
+                "{0}\n    + {0}\n    - ({0}\n           + {0}\n           + {0});\n",
+                term
+            )
+        );
+        assert_eq!(fmt(&fmt(&source)), fmt(&source));
+    }
+}
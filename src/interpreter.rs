@@ -0,0 +1,61 @@
+//! Evaluates an `Ast` directly, without generating code.
+//!
+//! The results are identical to those of compiling the `Ast` and running it on the `Vm`, as both
+//! apply operators through [`UnOpKind::evaluate`] and [`BinOpKind::evaluate`]. This makes the
+//! interpreter a reference against which the compiler and virtual machines can be tested, and a
+//! cheaper way to evaluate small programs that are only run once.
+//!
+//! [`UnOpKind::evaluate`]: crate::ast::UnOpKind::evaluate
+//! [`BinOpKind::evaluate`]: crate::ast::BinOpKind::evaluate
+
+use crate::ast::{Ast, DeclKind, Expr, ExprKind, StmtKind};
+
+/// Evaluates every declaration in `ast`, returning the values of its top-level expression
+/// statements in the order they were evaluated.
+pub fn interpret(ast: &Ast) -> Vec<f64> {
+    let mut values = vec![];
+    for decl in ast.decls() {
+        match decl.kind() {
+            DeclKind::Stmt(stmt) => match stmt.kind() {
+                StmtKind::Expr(expr) => values.push(evaluate(expr)),
+            },
+        }
+    }
+
+    values
+}
+
+/// Evaluates `expr`. An expression that failed to parse has no value, and evaluates to NaN as in
+/// the generated code.
+pub fn evaluate(expr: &Expr) -> f64 {
+    match expr.kind() {
+        ExprKind::Number(value) => *value,
+        ExprKind::Error => f64::NAN,
+        ExprKind::Unary(op, operand) => op.kind().evaluate(evaluate(operand)),
+        ExprKind::Binary(op, operand_1, operand_2) => {
+            // Evaluate left to right, as the generated code does.
+            let a = evaluate(operand_1);
+            let b = evaluate(operand_2);
+            op.kind().evaluate(a, b)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{codegen::CodeGenerator, parser::Parser, vm::Vm};
+
+    use super::interpret;
+
+    #[test]
+    fn matches_vm() {
+        let source = "1 + 2 * 3; -(4 - 5) / 6; 0 / 0; -0; 1 / -0; -7 % 3; 0.1 + 0.2;";
+        let ast = Parser::new(source).parse();
+        let expected = Vm::new(CodeGenerator::default().generate(&ast).unwrap())
+            .run()
+            .unwrap();
+
+        let bits = |values: Vec<f64>| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(interpret(&ast)), bits(expected));
+    }
+}
@@ -0,0 +1,417 @@
+//! A minimal JSON value, with a parser and a serializer through `Display`.
+
+use std::{
+    fmt::{Display, Formatter, Write},
+    ops::Index,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in the order they were written, which is kept when serializing.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn object<'a>(members: impl IntoIterator<Item = (&'a str, Value)>) -> Self {
+        Value::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    /// Returns the member named `key` if this is an object that has one.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns the number if it is a non-negative integer.
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64()
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+            .map(|n| n as usize)
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Parses a complete JSON document.
+    pub fn parse(source: &str) -> Result<Value, ParseError> {
+        let mut parser = Parser { source, pos: 0 };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos < source.len() {
+            return Err(parser.error());
+        }
+
+        Ok(value)
+    }
+}
+
+static NULL: Value = Value::Null;
+
+impl Index<&str> for Value {
+    type Output = Value;
+
+    /// Returns the member named `key`, or null if there isn't one.
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+impl Index<usize> for Value {
+    type Output = Value;
+
+    /// Returns the element at `idx`, or null if this is not an array with that many elements.
+    fn index(&self, idx: usize) -> &Value {
+        self.as_array()
+            .and_then(|values| values.get(idx))
+            .unwrap_or(&NULL)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Value::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl Display for Value {
+    /// Writes the value as compact JSON. Numbers that are not finite have no representation in
+    /// JSON, and are written as `null`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Value::Number(_) => f.write_str("null"),
+            Value::String(s) => write_string(f, s),
+            Value::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    value.fmt(f)?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(members) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    f.write_char(':')?;
+                    value.fmt(f)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter<'_>, s: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// The source was not valid JSON, first noticed at byte `offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    pub offset: usize,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid JSON at offset {}", self.offset)
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// The greatest depth to which arrays and objects may be nested.
+    const MAX_DEPTH: usize = 128;
+
+    fn value(&mut self, depth: usize) -> Result<Value, ParseError> {
+        if depth > Self::MAX_DEPTH {
+            return Err(self.error());
+        }
+
+        self.skip_whitespace();
+        match self.peek().ok_or_else(|| self.error())? {
+            'n' => self.keyword("null", Value::Null),
+            't' => self.keyword("true", Value::Bool(true)),
+            'f' => self.keyword("false", Value::Bool(false)),
+            '"' => Ok(Value::String(self.string()?)),
+            '[' => {
+                self.pos += 1;
+                let mut values = vec![];
+                if !self.eat(']') {
+                    loop {
+                        values.push(self.value(depth + 1)?);
+                        if self.eat(']') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Value::Array(values))
+            }
+            '{' => {
+                self.pos += 1;
+                let mut members = vec![];
+                if !self.eat('}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.expect(':')?;
+                        members.push((key, self.value(depth + 1)?));
+                        if self.eat('}') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Value::Object(members))
+            }
+            '-' | '0'..='9' => self.number(),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, ParseError> {
+        if !self.source[self.pos..].starts_with(keyword) {
+            return Err(self.error());
+        }
+        self.pos += keyword.len();
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        let rest = &self.source[start..];
+        let len = rest
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(rest.len());
+        let number = rest[..len].parse().map_err(|_| self.error())?;
+        self.pos += len;
+        Ok(Value::Number(number))
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        if !self.eat_exact('"') {
+            return Err(self.error());
+        }
+
+        let mut out = String::new();
+        loop {
+            let c = self.next().ok_or_else(|| self.error())?;
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let c = match self.next().ok_or_else(|| self.error())? {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.unicode_escape()?,
+                        _ => return Err(self.error()),
+                    };
+                    out.push(c);
+                }
+                c if c.is_control() => return Err(self.error()),
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// Reads the digits of a `\u` escape, and of the low surrogate that follows a high one.
+    fn unicode_escape(&mut self) -> Result<char, ParseError> {
+        let high = self.hex()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error());
+        }
+
+        if !self.source[self.pos..].starts_with("\\u") {
+            return Err(self.error());
+        }
+        self.pos += 2;
+        let low = self.hex()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error());
+        }
+
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+            .ok_or_else(|| self.error())
+    }
+
+    fn hex(&mut self) -> Result<u32, ParseError> {
+        let digits = self
+            .source
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error())?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.error())?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.source[self.pos..];
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Consumes `c` if it is the next character after any whitespace.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        self.eat_exact(c)
+    }
+
+    fn eat_exact(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += c.len_utf8();
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn error(&self) -> ParseError {
+        ParseError { offset: self.pos }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Value;
+
+    #[test]
+    fn round_trip() {
+        let source = r#"{"a":[1,-2.5,true,null],"b":"q\"\\\n\u0001é","c":{}}"#;
+        let value = Value::parse(source).unwrap();
+        assert_eq!(value.get("a").unwrap().as_array().unwrap().len(), 4);
+        assert_eq!(value.get("b").unwrap().as_str(), Some("q\"\\\n\u{1}é"));
+        assert_eq!(value.to_string(), source);
+    }
+
+    #[test]
+    fn escapes_and_errors() {
+        let value = Value::parse(r#" "\ud83d\ude00\u00e9\/" "#).unwrap();
+        assert_eq!(value.as_str(), Some("😀é/"));
+
+        for source in ["", "[1,]", "{\"a\" 1}", "\"\\ud83d\"", "01x", "[[[", "nul"] {
+            assert!(Value::parse(source).is_err(), "{}", source);
+        }
+        assert!(Value::parse(&"[".repeat(1000)).is_err());
+        assert_eq!(Value::Number(f64::NAN).to_string(), "null");
+    }
+}
@@ -2,6 +2,8 @@ mod cursor;
 pub mod span;
 pub mod token;
 
+use crate::diagnostics::{Diagnostic, Label};
+
 use self::{
     cursor::Cursor,
     span::Span,
@@ -11,15 +13,25 @@ use self::{
 #[derive(Debug)]
 pub struct Lexer<'a> {
     cursor: Cursor<'a>,
+    /// The span of every comment skipped so far, including the leading `//`.
+    comments: Vec<Span>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(source: &'a str) -> Self {
-        Self { cursor: Cursor::new(source) }
+        Self {
+            cursor: Cursor::new(source),
+            comments: vec![],
+        }
+    }
+
+    /// Comments skipped so far, in the order they appear in the source.
+    pub fn comments(&self) -> &[Span] {
+        &self.comments
     }
 
     pub fn next_token(&mut self) -> Result<Token<'a>, LexicalError> {
-        self.cursor.advance_while(|c| c.is_ascii_whitespace());
+        self.skip_trivia();
         self.cursor.reset_start_index();
 
         let kind = match self.cursor.advance() {
@@ -43,6 +55,11 @@ impl<'a> Lexer<'a> {
 
                 TokenKind::Number
             }
+            'a'..='z' | 'A'..='Z' | '_' => {
+                self.cursor
+                    .advance_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                TokenKind::Identifier
+            }
             Cursor::EOF_CHAR => TokenKind::Eof,
             _ => return Err(self.error(LexicalErrorKind::Unexpected)),
         };
@@ -50,6 +67,21 @@ impl<'a> Lexer<'a> {
         Ok(self.token(kind))
     }
 
+    /// Skips whitespace and line comments, recording the span of each comment.
+    fn skip_trivia(&mut self) {
+        loop {
+            self.cursor.advance_while(|c| c.is_ascii_whitespace());
+            if self.cursor.peek_first() != '/' || self.cursor.peek_second() != '/' {
+                break;
+            }
+
+            self.cursor.reset_start_index();
+            self.cursor.advance_while(|c| c != '\n');
+            let span = self.cursor.reset_span();
+            self.comments.push(span);
+        }
+    }
+
     fn token(&mut self, kind: TokenKind) -> Token<'a> {
         Token {
             lexeme: self.cursor.slice(),
@@ -72,15 +104,60 @@ pub struct LexicalError {
     pub kind: LexicalErrorKind,
 }
 
+impl LexicalError {
+    pub fn message(&self) -> &'static str {
+        match self.kind {
+            LexicalErrorKind::UnterminatedString => "unterminated string",
+            LexicalErrorKind::Unexpected => "unexpected character",
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn diagnostic(&self, source: &str) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.message()).with_code(self.code());
+        match self.kind {
+            LexicalErrorKind::Unexpected => {
+                let found = self.span.slice(source);
+                let diagnostic = diagnostic.with_label(Label::primary(
+                    self.span,
+                    format!("{:?} is not part of the language", found),
+                ));
+                match found {
+                    "#" => diagnostic.with_help("comments begin with `//`"),
+                    "×" => diagnostic.with_suggestion(self.span, "*", "use `*` to multiply"),
+                    "÷" => diagnostic.with_suggestion(self.span, "/", "use `/` to divide"),
+                    _ => diagnostic,
+                }
+            }
+            LexicalErrorKind::UnterminatedString => {
+                diagnostic.with_label(Label::primary(self.span, "string starts here"))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum LexicalErrorKind {
     UnterminatedString,
     Unexpected,
 }
 
+impl LexicalErrorKind {
+    /// The stable code identifying the error, which `calculator --explain` describes.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexicalErrorKind::Unexpected => "E0001",
+            LexicalErrorKind::UnterminatedString => "E0002",
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::lexer::token::TokenKind;
+    use crate::lexer::{span::Span, token::TokenKind};
 
     use super::{Lexer, LexicalError};
 
@@ -97,6 +174,18 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn identifier() -> Result<(), LexicalError> {
+        let mut lexer = Lexer::new("sqrt_2(x)");
+        let token = lexer.next_token()?;
+        assert_eq!(token.kind, TokenKind::Identifier);
+        assert_eq!(token.lexeme, "sqrt_2");
+        assert_eq!(lexer.next_token()?.kind, TokenKind::LParen);
+        assert_eq!(lexer.next_token()?.kind, TokenKind::Identifier);
+
+        Ok(())
+    }
+
     #[test]
     fn unexpected() -> Result<(), LexicalError> {
         let mut lexer = Lexer::new("10 + #");
@@ -106,5 +195,21 @@ mod test {
         assert_eq!(lexer.next_token()?.kind, TokenKind::Eof);
 
         Ok(())
-    }        
+    }
+
+    #[test]
+    fn comments() -> Result<(), LexicalError> {
+        let mut lexer = Lexer::new("// one\n1 / 2; // two\n//");
+        assert_eq!(lexer.next_token()?.kind, TokenKind::Number);
+        assert_eq!(lexer.next_token()?.kind, TokenKind::Slash);
+        assert_eq!(lexer.next_token()?.kind, TokenKind::Number);
+        assert_eq!(lexer.next_token()?.kind, TokenKind::Semicolon);
+        assert_eq!(lexer.next_token()?.kind, TokenKind::Eof);
+        assert_eq!(
+            lexer.comments(),
+            [Span::new(0, 6), Span::new(14, 20), Span::new(21, 23)]
+        );
+
+        Ok(())
+    }
 }
@@ -35,25 +35,6 @@ impl<'a> Cursor<'a> {
         it.next().unwrap_or(Self::EOF_CHAR)
     }
 
-    /// Advances if the predicate returns `true` and returns whether the cursor advanced.
-    pub fn advance_if<F>(&mut self, predicate: F) -> bool
-    where
-        F: FnOnce(char) -> bool + Copy,
-    {
-        let mut advanced = false;
-        if predicate(self.peek_first()) {
-            self.advance();
-            advanced = true;
-        }
-
-        advanced
-    }
-
-    /// Advances if the next character equals `c` and returns whether the cursor advanced.
-    pub fn advance_if_eq(&mut self, c: char) -> bool {
-        self.advance_if(|p| p == c)
-    }
-
     /// Advances while the next token matches the predicate and returns whether the cursor
     /// advanced.
     pub fn advance_while<F>(&mut self, predicate: F) -> bool
@@ -91,10 +72,6 @@ impl<'a> Cursor<'a> {
         &self.source[self.start_index()..self.current_index()]
     }
 
-    pub fn span(&mut self) -> Span {
-        Span::new(self.start, self.current_index())
-    }
-
     pub fn reset_span(&mut self) -> Span {
         Span::new(self.reset_start_index(), self.current_index())
     }
@@ -1,4 +1,4 @@
-use std::ops::Add;
+use std::{fmt::Display, ops::Add};
 
 /// Represents a region of the source code.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -21,6 +21,14 @@ impl Span {
         }
     }
 
+    /// Returns the smallest span containing both `a` and `b`.
+    pub fn cover(a: Span, b: Span) -> Span {
+        Self {
+            start: a.start.min(b.start),
+            end: a.end.max(b.end),
+        }
+    }
+
     pub fn start(&self) -> usize {
         self.start
     }
@@ -30,7 +38,7 @@ impl Span {
     }
 
     pub fn len(&self) -> usize {
-        self.end - self.start
+        self.end.saturating_sub(self.start)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -43,7 +51,7 @@ impl Span {
 
     /// Returns the number of the line on which the span begins.
     pub fn starting_line_number(self, source: &str) -> usize {
-        source[..self.start]
+        self.preceding(source)
             .chars()
             .filter(|c| *c == '\n')
             .count()
@@ -52,11 +60,45 @@ impl Span {
 
     /// Returns the number of the column on which the span begins.
     pub fn starting_column_number(self, source: &str) -> usize {
-        source[..self.start]
+        self.preceding(source)
             .chars()
             .rev()
             .take_while(|c| *c != '\n')
             .count()
             .add(1)
     }
+
+    /// Returns the source code before the span, ending at the nearest character boundary if the
+    /// span does not begin on one, such as when it was not produced from `source`.
+    fn preceding(self, source: &str) -> &str {
+        let mut end = self.start.min(source.len());
+        while !source.is_char_boundary(end) {
+            end -= 1;
+        }
+        &source[..end]
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Span;
+
+    #[test]
+    fn position_outside_source() {
+        // The span begins in the middle of the two byte 'é'.
+        let span = Span::new(4, 5);
+        assert_eq!(span.starting_line_number("1;\né;"), 2);
+        assert_eq!(span.starting_column_number("1;\né;"), 1);
+
+        let span = Span::new(100, 101);
+        assert_eq!(span.starting_line_number("1;\n2;"), 2);
+        assert_eq!(span.starting_column_number("1;\n2;"), 3);
+        assert_eq!(Span::new(3, 1).len(), 0);
+    }
 }
@@ -25,12 +25,22 @@ pub enum TokenKind {
     RParen,
     Semicolon,
     Number,
+    /// A name, such as `sqrt`. The language defines no names yet, so every one is unknown.
+    Identifier,
     Plus,
     Minus,
     Star,
     Slash,
     Percent,
 
+    /// Whitespace between tokens. The lexer skips trivia, so this and the following two kinds
+    /// only appear in the syntax tree.
+    Whitespace,
+    /// A `//` comment, up to but not including the end of the line.
+    Comment,
+    /// A character that does not begin any token.
+    Unknown,
+
     /// Never produced by the lexer, used only in places where we need a placeholder token. Should
     /// never be consumed by the parser.
     Dummy,
@@ -41,7 +51,15 @@ pub enum TokenKind {
 impl TokenKind {
     /// If the `lexeme`s of all `Token`s with this kind are identical.
     pub fn is_uniform(self) -> bool {
-        !matches!(self, Self::Number)
+        !matches!(
+            self,
+            Self::Number | Self::Identifier | Self::Whitespace | Self::Comment | Self::Unknown
+        )
+    }
+
+    /// If tokens of this kind have no meaning to the parser.
+    pub fn is_trivia(self) -> bool {
+        matches!(self, Self::Whitespace | Self::Comment | Self::Unknown)
     }
 }
 
@@ -52,11 +70,15 @@ impl Display for TokenKind {
             TokenKind::RParen => ")",
             TokenKind::Semicolon => ";",
             TokenKind::Number => "<number>",
+            TokenKind::Identifier => "<identifier>",
             TokenKind::Plus => "+",
             TokenKind::Minus => "-",
             TokenKind::Star => "*",
             TokenKind::Slash => "/",
             TokenKind::Percent => "%",
+            TokenKind::Whitespace => "<whitespace>",
+            TokenKind::Comment => "<comment>",
+            TokenKind::Unknown => "<unknown>",
             TokenKind::Dummy => "<dummy>",
             TokenKind::Eof => "<eof>",
         };
@@ -1,7 +1,21 @@
+pub mod assembler;
 pub mod ast;
 pub mod bytecode;
 pub mod codegen;
 pub mod diagnostics;
+pub mod disassembler;
+pub mod formatter;
+pub mod interpreter;
+pub mod json;
 pub mod lexer;
+pub mod lsp;
+pub mod optimizer;
 pub mod parser;
+pub mod printer;
+pub mod register;
+pub mod syntax;
+pub mod verifier;
 pub mod vm;
+
+#[cfg(test)]
+mod test;
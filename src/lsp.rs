@@ -0,0 +1,419 @@
+//! A Language Server Protocol server, run over stdio by the `calculator-lsp` binary.
+//!
+//! Documents are synchronized in full on every change, after which the errors from parsing them
+//! are published as diagnostics. Hovering over an expression shows its value, and documents can
+//! be formatted. The language has no variables, functions or built-ins yet, so go-to-definition
+//! and completion are not offered.
+
+pub mod position;
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    ast::{DeclKind, Expr, ExprKind, StmtKind},
+    formatter::format,
+    interpreter::evaluate,
+    json::Value,
+    lexer::span::Span,
+    parser::{Mode, Parser},
+};
+
+use self::position::{LineIndex, Position};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Reads the body of the next message, or `None` at the end of the input.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let Some(content_length) = content_length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing Content-Length header",
+        ));
+    };
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// The state of a session with a client.
+#[derive(Debug, Default)]
+pub struct Server {
+    /// The text of each open document, by URI.
+    documents: HashMap<String, String>,
+    shutdown: bool,
+    exit_code: Option<u8>,
+}
+
+impl Server {
+    /// The exit code the process should exit with, once the client has sent the `exit`
+    /// notification.
+    pub fn exit_code(&self) -> Option<u8> {
+        self.exit_code
+    }
+
+    /// Handles the body of a message from the client, returning the messages to send back.
+    pub fn handle_message(&mut self, body: &str) -> Vec<Value> {
+        match Value::parse(body) {
+            Ok(message) => self.handle(&message),
+            Err(err) => vec![error_response(Value::Null, PARSE_ERROR, &err.to_string())],
+        }
+    }
+
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let params = message.get("params").unwrap_or(&Value::Null);
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // A response, but the server never sends any requests.
+            return vec![];
+        };
+
+        let Some(id) = message.get("id").cloned() else {
+            return self.notification(method, params);
+        };
+
+        if self.shutdown {
+            return vec![error_response(id, INVALID_REQUEST, "server is shut down")];
+        }
+        match self.request(method, params) {
+            Ok(result) => vec![Value::object([
+                ("jsonrpc", "2.0".into()),
+                ("id", id),
+                ("result", result),
+            ])],
+            Err((code, message)) => vec![error_response(id, code, &message)],
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => Ok(Value::object([
+                (
+                    "capabilities",
+                    Value::object([
+                        // Full synchronization.
+                        ("textDocumentSync", 1i64.into()),
+                        ("hoverProvider", true.into()),
+                        ("documentFormattingProvider", true.into()),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Value::object([
+                        ("name", "calculator-lsp".into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ])),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/formatting" => self.formatting(params),
+            _ => Err((METHOD_NOT_FOUND, format!("unhandled method '{}'", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|document| document.get("uri"))
+            .and_then(Value::as_str);
+        match (method, uri) {
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params
+                    .get("textDocument")
+                    .and_then(|document| document.get("text"))
+                    .and_then(Value::as_str);
+                self.documents
+                    .insert(uri.to_owned(), text.unwrap_or_default().to_owned());
+                vec![self.diagnostics(uri)]
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                // With full synchronization the last change holds the whole text.
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Value::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Value::as_str);
+                let Some(text) = text else {
+                    return vec![];
+                };
+                self.documents.insert(uri.to_owned(), text.to_owned());
+                vec![self.diagnostics(uri)]
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(uri);
+                vec![self.diagnostics(uri)]
+            }
+            ("exit", _) => {
+                self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Returns a notification publishing the errors in the document at `uri`, or clearing them if
+    /// it has been closed.
+    fn diagnostics(&self, uri: &str) -> Value {
+        let source = self.documents.get(uri).map_or("", String::as_str);
+        let index = LineIndex::new(source);
+        let mut parser = Parser::new(source).with_mode(Mode::Lines);
+        parser.parse();
+
+        let diagnostics: Vec<_> = parser
+            .errors()
+            .iter()
+            .map(|err| {
+                Value::object([
+                    ("range", index.range(err.span())),
+                    // Error.
+                    ("severity", 1i64.into()),
+                    ("code", err.code().into()),
+                    ("source", "calculator".into()),
+                    ("message", err.message().into()),
+                ])
+            })
+            .collect();
+
+        Value::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            (
+                "params",
+                Value::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]),
+            ),
+        ])
+    }
+
+    /// Shows the value of the innermost expression under the cursor, unless it has errors.
+    fn hover(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let source = self.document(params)?;
+        let position = params
+            .get("position")
+            .and_then(Position::from_json)
+            .ok_or_else(invalid_params)?;
+        let index = LineIndex::new(source);
+        let offset = index.offset(position);
+
+        let ast = Parser::new(source).with_mode(Mode::Lines).parse();
+        let expr = ast.decls().iter().find_map(|decl| match decl.kind() {
+            DeclKind::Stmt(stmt) => match stmt.kind() {
+                StmtKind::Expr(expr) => innermost(expr, offset),
+            },
+        });
+        let Some(expr) = expr.filter(|expr| !expr.has_error()) else {
+            return Ok(Value::Null);
+        };
+
+        Ok(Value::object([
+            (
+                "contents",
+                Value::object([
+                    ("kind", "plaintext".into()),
+                    ("value", evaluate(expr).to_string().into()),
+                ]),
+            ),
+            ("range", index.range(expr.span())),
+        ]))
+    }
+
+    /// Replaces the whole document with its formatted source, unless it fails to parse.
+    fn formatting(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let source = self.document(params)?;
+        let mut parser = Parser::new(source).with_mode(Mode::Lines);
+        let ast = parser.parse();
+        if !parser.errors().is_empty() {
+            return Ok(Value::Null);
+        }
+
+        let formatted = format(&ast, source);
+        if formatted == source {
+            return Ok(Value::Array(vec![]));
+        }
+
+        let index = LineIndex::new(source);
+        Ok(Value::Array(vec![Value::object([
+            ("range", index.range(Span::new(0, source.len()))),
+            ("newText", formatted.into()),
+        ])]))
+    }
+
+    /// Returns the text of the open document named by the request's `textDocument` parameter.
+    fn document(&self, params: &Value) -> Result<&str, (i64, String)> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|document| document.get("uri"))
+            .and_then(Value::as_str)
+            .ok_or_else(invalid_params)?;
+        self.documents
+            .get(uri)
+            .map(String::as_str)
+            .ok_or_else(|| (INVALID_PARAMS, format!("document '{}' is not open", uri)))
+    }
+}
+
+/// Returns the innermost expression in `expr` whose span contains `offset`.
+fn innermost(expr: &Expr, offset: usize) -> Option<&Expr> {
+    let span = expr.span();
+    if offset < span.start() || offset >= span.end() {
+        return None;
+    }
+
+    let operand = match expr.kind() {
+        ExprKind::Number(_) | ExprKind::Error => None,
+        ExprKind::Unary(_, operand) => innermost(operand, offset),
+        ExprKind::Binary(_, operand_1, operand_2) => {
+            innermost(operand_1, offset).or_else(|| innermost(operand_2, offset))
+        }
+    };
+
+    Some(operand.unwrap_or(expr))
+}
+
+fn invalid_params() -> (i64, String) {
+    (INVALID_PARAMS, "invalid params".to_owned())
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    Value::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Value::object([("code", code.into()), ("message", message.into())]),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use crate::json::Value;
+
+    use super::{read_message, write_message, Server};
+
+    fn request(server: &mut Server, method: &str, params: &str) -> Vec<Value> {
+        server.handle_message(&format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"{}","params":{}}}"#,
+            method, params
+        ))
+    }
+
+    fn notify(server: &mut Server, method: &str, params: &str) -> Vec<Value> {
+        server.handle_message(&format!(
+            r#"{{"jsonrpc":"2.0","method":"{}","params":{}}}"#,
+            method, params
+        ))
+    }
+
+    #[test]
+    fn session() {
+        let mut server = Server::default();
+        let response = &request(&mut server, "initialize", "{}")[0];
+        let capabilities = &response["result"]["capabilities"];
+        assert_eq!(capabilities["hoverProvider"], Value::Bool(true));
+        assert!(capabilities.get("definitionProvider").is_none());
+        assert!(capabilities.get("completionProvider").is_none());
+
+        // 'é' is two bytes but one UTF-16 code unit.
+        let messages = notify(
+            &mut server,
+            "textDocument/didOpen",
+            r#"{"textDocument":{"uri":"file:///a.calc","text":"é;\n1 + # 2;"}}"#,
+        );
+        let diagnostics = &messages[0]["params"]["diagnostics"];
+        assert_eq!(
+            diagnostics[1]["range"]["start"].to_string(),
+            r#"{"line":1,"character":4}"#
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            r#"{"range":{"start":{"line":0,"character":0},"end":{"line":0,"character":1}},"severity":1,"code":"E0001","source":"calculator","message":"unexpected character"}"#
+        );
+
+        notify(
+            &mut server,
+            "textDocument/didChange",
+            r#"{"textDocument":{"uri":"file:///a.calc"},"contentChanges":[{"text":"1+2*3;"}]}"#,
+        );
+        let hover = |server: &mut Server, character| {
+            request(
+                server,
+                "textDocument/hover",
+                &format!(
+                    r#"{{"textDocument":{{"uri":"file:///a.calc"}},"position":{{"line":0,"character":{}}}}}"#,
+                    character
+                ),
+            )
+            .remove(0)
+        };
+        assert_eq!(
+            hover(&mut server, 3)["result"]["contents"]["value"].as_str(),
+            Some("6")
+        );
+        assert_eq!(
+            hover(&mut server, 1)["result"]["contents"]["value"].as_str(),
+            Some("7")
+        );
+        assert_eq!(hover(&mut server, 6)["result"], Value::Null);
+
+        let edits = &request(
+            &mut server,
+            "textDocument/formatting",
+            r#"{"textDocument":{"uri":"file:///a.calc"},"options":{}}"#,
+        )[0]["result"];
+        assert_eq!(edits[0]["newText"].as_str(), Some("1 + 2 * 3;\n"));
+
+        let error = &request(&mut server, "textDocument/definition", "{}")[0];
+        assert_eq!(error["error"]["code"].as_f64(), Some(-32601.0));
+
+        request(&mut server, "shutdown", "null");
+        assert_eq!(server.exit_code(), None);
+        notify(&mut server, "exit", "null");
+        assert_eq!(server.exit_code(), Some(0));
+    }
+
+    #[test]
+    fn framing() {
+        let mut out = vec![];
+        write_message(&mut out, &Value::from("é")).unwrap();
+        assert_eq!(out, b"Content-Length: 4\r\n\r\n\"\xc3\xa9\"");
+
+        let mut input = &b"Content-Type: x\r\ncontent-length: 2\r\n\r\n{}"[..];
+        assert_eq!(read_message(&mut input).unwrap().as_deref(), Some("{}"));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+}
@@ -0,0 +1,128 @@
+//! Conversion between byte offsets and LSP positions.
+//!
+//! LSP positions are a zero-based line and a character offset within the line counted in UTF-16
+//! code units, where lines may end with `\n`, `\r\n` or `\r`.
+
+use crate::{json::Value, lexer::span::Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl Position {
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            line: value.get("line")?.as_usize()?,
+            character: value.get("character")?.as_usize()?,
+        })
+    }
+
+    pub fn to_json(self) -> Value {
+        Value::object([
+            ("line", self.line.into()),
+            ("character", self.character.into()),
+        ])
+    }
+}
+
+/// The start of every line in a document, for converting between offsets and positions.
+#[derive(Debug)]
+pub struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        let bytes = source.as_bytes();
+        for (i, byte) in bytes.iter().enumerate() {
+            let ends_line = match byte {
+                b'\n' => true,
+                b'\r' => bytes.get(i + 1) != Some(&b'\n'),
+                _ => false,
+            };
+            if ends_line {
+                line_starts.push(i + 1);
+            }
+        }
+
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    /// Returns the position of `offset`, which is clamped to the source and rounded down to the
+    /// nearest character boundary.
+    pub fn position(&self, offset: usize) -> Position {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let character = self.source[self.line_starts[line]..offset]
+            .encode_utf16()
+            .count();
+        Position { line, character }
+    }
+
+    /// Returns the offset of `position`. Positions past the end of their line are clamped to it,
+    /// and positions in the middle of a character are rounded down to its start.
+    pub fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.line_starts.get(position.line) else {
+            return self.source.len();
+        };
+        let end = self
+            .line_starts
+            .get(position.line + 1)
+            .copied()
+            .unwrap_or(self.source.len());
+        let line = self.source[start..end].trim_end_matches(['\n', '\r']);
+
+        let mut units = 0;
+        for (i, c) in line.char_indices() {
+            units += c.len_utf16();
+            if units > position.character {
+                return start + i;
+            }
+        }
+
+        start + line.len()
+    }
+
+    pub fn range(&self, span: Span) -> Value {
+        Value::object([
+            ("start", self.position(span.start()).to_json()),
+            ("end", self.position(span.end()).to_json()),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LineIndex, Position};
+
+    #[test]
+    fn utf16() {
+        // '😀' is four bytes and two UTF-16 code units, and 'é' is two bytes and one unit.
+        let source = "😀é1;\r\n2;\r3;";
+        let index = LineIndex::new(source);
+        let position = |line, character| Position { line, character };
+
+        assert_eq!(index.position(4), position(0, 2));
+        assert_eq!(index.position(6), position(0, 3));
+        assert_eq!(index.position(11), position(1, 1));
+        assert_eq!(index.position(14), position(2, 1));
+        assert_eq!(index.position(100), position(2, 2));
+
+        assert_eq!(index.offset(position(0, 3)), 6);
+        assert_eq!(index.offset(position(0, 1)), 0);
+        assert_eq!(index.offset(position(0, 50)), 8);
+        assert_eq!(index.offset(position(2, 1)), 14);
+        assert_eq!(index.offset(position(9, 0)), source.len());
+    }
+}
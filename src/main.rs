@@ -1,21 +1,614 @@
-use calculator::{codegen::CodeGenerator, parser::Parser, vm::Vm};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
-fn main() {
-    let args: Vec<_> = std::env::args().collect();
-    if args.len() != 2 {
-        eprintln!("usage: {} <expression>", env!("CARGO_BIN_NAME"));
-        return;
+use calculator::{
+    ast::Ast,
+    bytecode::Bytecode,
+    codegen::{CodeGenerator, CodegenError},
+    diagnostics::{self, apply_suggestions, Diagnostic},
+    disassembler::Disassembler,
+    formatter::format,
+    interpreter::interpret,
+    lexer::{token::TokenKind, Lexer},
+    optimizer::{fold::fold_constants, peephole},
+    parser::{Mode, ParseError, Parser},
+    register,
+    verifier::verify,
+    vm::{Vm, VmError},
+};
+
+/// The command line was malformed, or the input could not be read.
+const EXIT_USAGE: u8 = 1;
+/// The source contained a character that does not begin any token.
+const EXIT_LEXICAL_ERROR: u8 = 2;
+/// The source could be tokenized but not parsed.
+const EXIT_SYNTAX_ERROR: u8 = 3;
+/// The program was well formed but failed while executing.
+const EXIT_RUNTIME_ERROR: u8 = 4;
+/// A bytecode file was corrupted, written by an incompatible version, or failed verification.
+const EXIT_INVALID_BYTECODE: u8 = 5;
+/// `fmt --check` found source that was not formatted.
+const EXIT_UNFORMATTED: u8 = 6;
+/// The program was well formed but too large to compile to bytecode.
+const EXIT_COMPILE_ERROR: u8 = 7;
+
+const USAGE: &str = "\
+usage: calculator [-O0|-O1] [--vm stack|register|tree] [--emit tokens|ast|bytecode] [--fix]
+                  [--error-format human|json] <expression | file.calc | ->
+       calculator compile [-O0|-O1] [--error-format human|json] <file.calc | -> [-o <file.calcb>]
+       calculator run [--error-format human|json] <file.calcb>
+       calculator fmt [--check] [--error-format human|json] <file.calc | ->
+       calculator --explain <code>";
+
+/// A compiler stage whose output can be printed instead of running the program.
+#[derive(Debug, Clone, Copy)]
+enum Emit {
+    Tokens,
+    Ast,
+    Bytecode,
+}
+
+/// The virtual machine used to evaluate source code.
+#[derive(Debug, Clone, Copy)]
+enum Backend {
+    Stack,
+    Register,
+    /// Walk the syntax tree instead of generating code.
+    Tree,
+}
+
+/// How errors in the source are written to stderr.
+#[derive(Debug, Clone, Copy)]
+enum ErrorFormat {
+    /// Rendered against the source for people to read.
+    Human,
+    /// One JSON object per line, for tools.
+    Json,
+}
+
+#[derive(Debug)]
+enum Command {
+    /// Evaluate source code, or print one of its compiler stages.
+    Eval {
+        emit: Option<Emit>,
+        backend: Backend,
+        /// Whether to first apply the fixes suggested for errors in the source.
+        fix: bool,
+    },
+    /// Compile source code to a bytecode file.
+    Compile { output: Option<PathBuf> },
+    /// Run a bytecode file.
+    Run,
+    /// Format source code, or with `check` only report whether it is formatted.
+    Fmt { check: bool },
+    /// Print the explanation of an error code.
+    Explain,
+}
+
+#[derive(Debug)]
+struct Options {
+    command: Command,
+    /// Whether to run the optimization passes when compiling, set by `-O1` and cleared by `-O0`.
+    optimize: bool,
+    error_format: ErrorFormat,
+    input: String,
+}
+
+impl Options {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.peekable();
+        let mut command = match args.peek().map(String::as_str) {
+            Some("compile") => Command::Compile { output: None },
+            Some("run") => Command::Run,
+            Some("fmt") => Command::Fmt { check: false },
+            Some("--explain") => Command::Explain,
+            _ => Command::Eval {
+                emit: None,
+                backend: Backend::Stack,
+                fix: false,
+            },
+        };
+        if !matches!(command, Command::Eval { .. }) {
+            args.next();
+        }
+
+        let mut optimize = true;
+        let mut error_format = ErrorFormat::Human;
+        let mut input = None;
+        while let Some(arg) = args.next() {
+            match (arg.as_str(), &mut command) {
+                ("-O0" | "-O1", Command::Eval { .. } | Command::Compile { .. }) => {
+                    optimize = arg == "-O1";
+                }
+                ("--vm", Command::Eval { backend, .. }) => {
+                    let name = args.next().ok_or("'--vm' requires a value")?;
+                    *backend = match name.as_str() {
+                        "stack" => Backend::Stack,
+                        "register" => Backend::Register,
+                        "tree" => Backend::Tree,
+                        _ => return Err(format!("unknown vm '{}'", name)),
+                    };
+                }
+                ("--emit", Command::Eval { emit, .. }) => {
+                    let stage = args.next().ok_or("'--emit' requires a value")?;
+                    *emit = Some(match stage.as_str() {
+                        "tokens" => Emit::Tokens,
+                        "ast" => Emit::Ast,
+                        "bytecode" => Emit::Bytecode,
+                        _ => return Err(format!("unknown stage '{}'", stage)),
+                    });
+                }
+                ("--error-format", command) if !matches!(command, Command::Explain) => {
+                    let name = args.next().ok_or("'--error-format' requires a value")?;
+                    error_format = match name.as_str() {
+                        "human" => ErrorFormat::Human,
+                        "json" => ErrorFormat::Json,
+                        _ => return Err(format!("unknown error format '{}'", name)),
+                    };
+                }
+                ("--fix", Command::Eval { fix, .. }) => *fix = true,
+                ("--check", Command::Fmt { check }) => *check = true,
+                ("-o", Command::Compile { output }) => {
+                    *output = Some(args.next().ok_or("'-o' requires a value")?.into());
+                }
+                _ if input.is_none() => input = Some(arg),
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+
+        Ok(Self {
+            command,
+            optimize,
+            error_format,
+            input: input.ok_or("missing input")?,
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            eprintln!("{}", USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let error_format = options.error_format;
+    match options.command {
+        Command::Eval { emit, backend, fix } => eval(
+            &options.input,
+            emit,
+            backend,
+            fix,
+            options.optimize,
+            error_format,
+        ),
+        Command::Compile { output } => {
+            compile(&options.input, output, options.optimize, error_format)
+        }
+        Command::Run => run(&options.input, error_format),
+        Command::Fmt { check } => fmt(&options.input, check, error_format),
+        Command::Explain => explain(&options.input),
+    }
+}
+
+fn eval(
+    input: &str,
+    emit: Option<Emit>,
+    backend: Backend,
+    fix: bool,
+    optimize: bool,
+    error_format: ErrorFormat,
+) -> ExitCode {
+    let source = match read_source(input, error_format) {
+        Ok(source) => source,
+        Err(code) => return code,
+    };
+    let source = if fix {
+        match fix_source(input, source, error_format) {
+            Ok(source) => source,
+            Err(code) => return code,
+        }
+    } else {
+        source
+    };
+
+    if let Some(Emit::Tokens) = emit {
+        return emit_tokens(&source, error_format);
+    }
+
+    let ast = match parse(&source, mode(input), error_format) {
+        Ok(ast) => ast,
+        Err(code) => return code,
+    };
+
+    if let Some(Emit::Ast) = emit {
+        print!("{}", ast.dump());
+        return ExitCode::SUCCESS;
     }
 
-    let source = args[1].as_str();
-    let mut parser = Parser::new(source);
+    if let (Backend::Tree, None) = (backend, emit) {
+        for value in interpret(&ast) {
+            println!("{}", value);
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    if let Backend::Register = backend {
+        let ast = if optimize { fold_constants(&ast) } else { ast };
+        let program = match register::codegen::CodeGenerator::default().generate(&ast) {
+            Ok(program) => program,
+            Err(err) => return compile_error(&err, &source, error_format),
+        };
+        if let Some(Emit::Bytecode) = emit {
+            print!("{}", program);
+            return ExitCode::SUCCESS;
+        }
+        return print_results(
+            register::vm::Vm::new(program).run(),
+            Some(&source),
+            error_format,
+        );
+    }
+
+    let bytecode = match generate(&ast, optimize, &source, error_format) {
+        Ok(bytecode) => bytecode,
+        Err(code) => return code,
+    };
+    if let Some(Emit::Bytecode) = emit {
+        print!("{}", Disassembler::new(&bytecode).with_source(&source));
+        return ExitCode::SUCCESS;
+    }
+
+    execute(bytecode, Some(&source), error_format)
+}
+
+fn compile(
+    input: &str,
+    output: Option<PathBuf>,
+    optimize: bool,
+    error_format: ErrorFormat,
+) -> ExitCode {
+    let Some(output) = output.or_else(|| {
+        let path = Path::new(input);
+        (path.extension()? == "calc").then(|| path.with_extension("calcb"))
+    }) else {
+        report_error(
+            "an output file must be given with '-o'".to_owned(),
+            error_format,
+        );
+        return ExitCode::from(EXIT_USAGE);
+    };
+
+    let source = match read_source(input, error_format) {
+        Ok(source) => source,
+        Err(code) => return code,
+    };
+
+    let ast = match parse(&source, mode(input), error_format) {
+        Ok(ast) => ast,
+        Err(code) => return code,
+    };
+
+    let bytecode = match generate(&ast, optimize, &source, error_format) {
+        Ok(bytecode) => bytecode,
+        Err(code) => return code,
+    };
+    if let Err(err) = std::fs::write(&output, bytecode.serialize()) {
+        let message = format!("could not write '{}': {}", output.display(), err);
+        report_error(message, error_format);
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Generates bytecode for `ast`, first running the optimization passes if `optimize` is set, and
+/// reporting an error against `source` if the program can't be compiled.
+fn generate(
+    ast: &Ast,
+    optimize: bool,
+    source: &str,
+    error_format: ErrorFormat,
+) -> Result<Bytecode, ExitCode> {
+    let bytecode = if optimize {
+        CodeGenerator::default()
+            .generate(&fold_constants(ast))
+            .map(|bytecode| peephole::optimize(&bytecode))
+    } else {
+        CodeGenerator::default().generate(ast)
+    };
+
+    bytecode.map_err(|err| compile_error(&err, source, error_format))
+}
+
+fn compile_error(err: &CodegenError, source: &str, error_format: ErrorFormat) -> ExitCode {
+    report(&err.diagnostic(), source, error_format);
+    suggest_explain(err.code(), error_format);
+    ExitCode::from(EXIT_COMPILE_ERROR)
+}
+
+fn run(input: &str, error_format: ErrorFormat) -> ExitCode {
+    let bytes = match std::fs::read(input) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            report_error(format!("could not read '{}': {}", input, err), error_format);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let bytecode = match Bytecode::deserialize(&bytes) {
+        Ok(bytecode) => bytecode,
+        Err(err) => {
+            let message = format!("could not load '{}': {}", input, err);
+            report(
+                &Diagnostic::error(message).with_code(err.code()),
+                "",
+                error_format,
+            );
+            suggest_explain(err.code(), error_format);
+            return ExitCode::from(EXIT_INVALID_BYTECODE);
+        }
+    };
+
+    if let Err(err) = verify(&bytecode) {
+        let message = format!("invalid bytecode in '{}': {}", input, err);
+        report(
+            &Diagnostic::error(message).with_code(err.code()),
+            "",
+            error_format,
+        );
+        suggest_explain(err.code(), error_format);
+        return ExitCode::from(EXIT_INVALID_BYTECODE);
+    }
+
+    execute(bytecode, None, error_format)
+}
+
+/// Formats the source, rewriting it in place if it is a file and otherwise printing it.
+fn fmt(input: &str, check: bool, error_format: ErrorFormat) -> ExitCode {
+    let source = match read_source(input, error_format) {
+        Ok(source) => source,
+        Err(code) => return code,
+    };
+
+    let ast = match parse(&source, mode(input), error_format) {
+        Ok(ast) => ast,
+        Err(code) => return code,
+    };
+
+    let formatted = format(&ast, &source);
+    if check {
+        if formatted == source {
+            return ExitCode::SUCCESS;
+        }
+        if is_source_file(input) {
+            report_error(format!("'{}' is not formatted", input), error_format);
+        } else {
+            report_error("input is not formatted".to_owned(), error_format);
+        }
+        return ExitCode::from(EXIT_UNFORMATTED);
+    }
+
+    if !is_source_file(input) {
+        print!("{}", formatted);
+    } else if formatted != source {
+        if let Err(err) = std::fs::write(input, formatted) {
+            report_error(
+                format!("could not write '{}': {}", input, err),
+                error_format,
+            );
+            return ExitCode::from(EXIT_USAGE);
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Runs `bytecode`, reporting a runtime error against `source` if it was compiled from it.
+fn execute(bytecode: Bytecode, source: Option<&str>, error_format: ErrorFormat) -> ExitCode {
+    print_results(Vm::new(bytecode).run(), source, error_format)
+}
+
+/// Prints the values a program emitted, or reports the runtime error it failed with against
+/// `source` if it was compiled from it.
+fn print_results(
+    result: Result<Vec<f64>, VmError>,
+    source: Option<&str>,
+    error_format: ErrorFormat,
+) -> ExitCode {
+    match result {
+        Ok(values) => {
+            for value in values {
+                println!("{}", value);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            match source {
+                Some(source) => report(&err.diagnostic(), source, error_format),
+                None => {
+                    let diagnostic = Diagnostic::error(err.message()).with_code(err.code());
+                    report(&diagnostic, "", error_format);
+                }
+            }
+            suggest_explain(err.code(), error_format);
+            ExitCode::from(EXIT_RUNTIME_ERROR)
+        }
+    }
+}
+
+/// Parses `source`, reporting every error and returning the exit code for the first.
+fn parse(source: &str, mode: Mode, error_format: ErrorFormat) -> Result<Ast, ExitCode> {
+    let mut parser = Parser::new(source).with_mode(mode);
     let ast = parser.parse();
-    if ast.complete() {
-        let bytecode = CodeGenerator::default().generate(&ast);
-        let mut vm = Vm::new(bytecode);
-        match vm.run() {
-            Ok(value) => println!("{}", value),
-            Err(err) => eprintln!("runtime error: {:?}", err),
+    for err in parser.errors() {
+        report(&err.diagnostic(source), source, error_format);
+    }
+
+    match parser.errors().first() {
+        Some(err) => {
+            suggest_explain(err.code(), error_format);
+            Err(parse_error_exit_code(err))
+        }
+        None => Ok(ast),
+    }
+}
+
+fn report(diagnostic: &Diagnostic, source: &str, error_format: ErrorFormat) {
+    match error_format {
+        ErrorFormat::Human => diagnostics::report(diagnostic, source),
+        ErrorFormat::Json => eprintln!("{}", diagnostic.to_json(source)),
+    }
+}
+
+/// Reports an error that has no part of the source to point at.
+fn report_error(message: String, error_format: ErrorFormat) {
+    report(&Diagnostic::error(message), "", error_format);
+}
+
+/// Points people reading the errors at the explanation of `code`.
+fn suggest_explain(code: &str, error_format: ErrorFormat) {
+    if let ErrorFormat::Human = error_format {
+        eprintln!(
+            "for more information about this error, try `calculator --explain {}`",
+            code
+        );
+    }
+}
+
+fn explain(code: &str) -> ExitCode {
+    match diagnostics::explain(&code.to_ascii_uppercase()) {
+        Some(explanation) => {
+            print!("{}", explanation);
+            ExitCode::SUCCESS
+        }
+        None => {
+            eprintln!("error: no error code '{}'", code);
+            ExitCode::from(EXIT_USAGE)
+        }
+    }
+}
+
+fn parse_error_exit_code(err: &ParseError) -> ExitCode {
+    ExitCode::from(match err {
+        ParseError::LexicalError(_) => EXIT_LEXICAL_ERROR,
+        ParseError::SyntacticError(_) => EXIT_SYNTAX_ERROR,
+    })
+}
+
+/// Applies the fixes suggested for errors in `source`, rewriting `input` if it is a file and
+/// otherwise printing the fixed source. Fixing one error can reveal another that recovering from
+/// it hid, so this is repeated until no more fixes are suggested.
+fn fix_source(
+    input: &str,
+    mut source: String,
+    error_format: ErrorFormat,
+) -> Result<String, ExitCode> {
+    const MAX_PASSES: usize = 16;
+
+    let mut fixed = 0;
+    for _ in 0..MAX_PASSES {
+        let mut parser = Parser::new(&source).with_mode(mode(input));
+        parser.parse();
+        let suggestions: Vec<_> = parser
+            .errors()
+            .iter()
+            .flat_map(|err| err.diagnostic(&source).suggestions().to_vec())
+            .collect();
+
+        let (next, applied) = apply_suggestions(&source, &suggestions);
+        if applied == 0 {
+            break;
+        }
+        source = next;
+        fixed += applied;
+    }
+
+    if fixed == 0 {
+        return Ok(source);
+    }
+
+    if is_source_file(input) {
+        if let Err(err) = std::fs::write(input, &source) {
+            report_error(
+                format!("could not write '{}': {}", input, err),
+                error_format,
+            );
+            return Err(ExitCode::from(EXIT_USAGE));
         }
+        eprintln!("fixed {} error(s) in '{}'", fixed, input);
+    } else {
+        // There is no file to show the fixes in, so they are shown before evaluating them.
+        eprintln!(
+            "fixed {} error(s) in the input, evaluating instead:\n{}",
+            fixed,
+            source.trim_end()
+        );
+    }
+
+    Ok(source)
+}
+
+/// Interprets `arg` as `-` for stdin, a path to a `.calc` file, or otherwise as the source itself.
+fn read_source(arg: &str, error_format: ErrorFormat) -> Result<String, ExitCode> {
+    let source = if arg == "-" {
+        let mut source = String::new();
+        std::io::stdin().read_to_string(&mut source).map(|_| source)
+    } else if is_source_file(arg) {
+        std::fs::read_to_string(arg)
+    } else {
+        Ok(arg.to_owned())
+    };
+
+    source.map_err(|err| {
+        report_error(format!("could not read '{}': {}", arg, err), error_format);
+        ExitCode::from(EXIT_USAGE)
+    })
+}
+
+/// Source given on the command line is a single expression that may leave out its `;`, while
+/// scripts read from files or stdin may end statements at the end of each line.
+fn mode(arg: &str) -> Mode {
+    if arg == "-" || is_source_file(arg) {
+        Mode::Lines
+    } else {
+        Mode::Expression
     }
 }
+
+fn is_source_file(arg: &str) -> bool {
+    Path::new(arg).extension().is_some_and(|ext| ext == "calc")
+}
+
+/// Prints every token in `source`, reporting and skipping over lexical errors.
+fn emit_tokens(source: &str, error_format: ErrorFormat) -> ExitCode {
+    let mut lexer = Lexer::new(source);
+    let mut exit_code = ExitCode::SUCCESS;
+    loop {
+        match lexer.next_token() {
+            Ok(token) => {
+                println!(
+                    "{:<10} {:<16} {}",
+                    format!("{:?}", token.kind),
+                    format!("{:?}", token.lexeme),
+                    token.span
+                );
+                if token.kind == TokenKind::Eof {
+                    break;
+                }
+            }
+            Err(err) => {
+                report(&err.diagnostic(source), source, error_format);
+                exit_code = ExitCode::from(EXIT_LEXICAL_ERROR);
+            }
+        }
+    }
+
+    exit_code
+}
@@ -0,0 +1,87 @@
+//! Constant folding over the `Ast`.
+//!
+//! Every operator is evaluated with the same `f64` arithmetic the `Vm` uses, and none of them can
+//! fail at runtime, so any subexpression whose operands are numbers is replaced by its value. As
+//! the language has no variables, this folds every expression down to a single number. A folded
+//! expression keeps the span of the expression it replaced.
+
+use crate::ast::{Ast, Decl, DeclKind, Expr, ExprKind, Stmt, StmtKind};
+
+pub fn fold_constants(ast: &Ast) -> Ast {
+    let decls = ast.decls().iter().map(fold_decl).collect();
+    Ast::new(decls, ast.complete())
+}
+
+fn fold_decl(decl: &Decl) -> Decl {
+    match decl.kind() {
+        DeclKind::Stmt(stmt) => Decl::new(decl.span(), DeclKind::Stmt(Box::new(fold_stmt(stmt)))),
+    }
+}
+
+fn fold_stmt(stmt: &Stmt) -> Stmt {
+    match stmt.kind() {
+        StmtKind::Expr(expr) => Stmt::new(stmt.span(), StmtKind::Expr(Box::new(fold_expr(expr)))),
+    }
+}
+
+pub fn fold_expr(expr: &Expr) -> Expr {
+    let kind = match expr.kind() {
+        ExprKind::Number(_) | ExprKind::Error => return expr.clone(),
+        ExprKind::Unary(op, operand) => {
+            let operand = fold_expr(operand);
+            match operand.kind() {
+                ExprKind::Number(value) => ExprKind::Number(op.kind().evaluate(*value)),
+                _ => ExprKind::Unary(*op, Box::new(operand)),
+            }
+        }
+        ExprKind::Binary(op, operand_1, operand_2) => {
+            let operand_1 = fold_expr(operand_1);
+            let operand_2 = fold_expr(operand_2);
+            match (operand_1.kind(), operand_2.kind()) {
+                (ExprKind::Number(a), ExprKind::Number(b)) => {
+                    ExprKind::Number(op.kind().evaluate(*a, *b))
+                }
+                _ => ExprKind::Binary(*op, Box::new(operand_1), Box::new(operand_2)),
+            }
+        }
+    };
+
+    Expr::new(expr.span(), kind)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ast::{DeclKind, ExprKind, StmtKind},
+        lexer::span::Span,
+        parser::Parser,
+    };
+
+    use super::fold_constants;
+
+    /// Folds the first statement of `source`, returning its value and span.
+    fn fold(source: &str) -> (f64, Span) {
+        let ast = fold_constants(&Parser::new(source).parse());
+        let DeclKind::Stmt(stmt) = ast.decls()[0].kind();
+        let StmtKind::Expr(expr) = stmt.kind();
+        match expr.kind() {
+            ExprKind::Number(value) => (*value, expr.span()),
+            kind => panic!("expected a number, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn folds_to_number() {
+        assert_eq!(fold("2 * 3.5 + 1;"), (8.0, Span::new(0, 11)));
+        assert_eq!(fold("-(1 - 3);"), (2.0, Span::new(0, 7)));
+    }
+
+    #[test]
+    fn float_semantics() {
+        assert!(fold("0 / 0;").0.is_nan());
+        assert_eq!(fold("1 / 0;").0, f64::INFINITY);
+        assert!(fold("-0;").0.is_sign_negative());
+        assert!(fold("-0 + 0;").0.is_sign_positive());
+        assert_eq!(fold("-7 % 3;").0, -1.0);
+    }
+}
@@ -0,0 +1,170 @@
+//! Peephole optimization over `Bytecode`.
+//!
+//! Instructions are appended to the output one at a time, and after each the tail of the output
+//! is rewritten for as long as one of these patterns matches:
+//!
+//! - `Constant; Pop` is removed.
+//! - `Negate; Negate` is removed.
+//! - `Constant c; Negate` becomes `Constant -c`.
+//! - `Constant c` followed by `Add`, `Subtract`, `Multiply` or `Divide` becomes the corresponding
+//!   superinstruction taking `c` as its operand, such as `AddConstant c`.
+//!
+//! Every rewrite produces exactly the same values as the code it replaces. A rewritten instruction
+//! takes the span of the instruction whose result it computes, so runtime errors still point at
+//! the same source.
+
+use crate::{
+    bytecode::{Bytecode, Instruction, Opcode},
+    lexer::span::Span,
+    verifier,
+};
+
+pub fn optimize(bytecode: &Bytecode) -> Bytecode {
+    let mut constants = bytecode.constants().to_vec();
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while offset < bytecode.as_ref().len() {
+        // Leave code we can't make sense of for the verifier to reject.
+        let Ok(instruction) = bytecode.decode(offset) else {
+            return bytecode.clone();
+        };
+        if instruction
+            .operand
+            .is_some_and(|idx| idx as usize >= constants.len())
+        {
+            return bytecode.clone();
+        }
+        let span = bytecode.span(offset).unwrap_or(Span::new(0, 0));
+        instructions.push((instruction, span));
+        while simplify(&mut instructions, &mut constants) {}
+
+        offset += instruction.size();
+    }
+
+    // Rewrites can leave constants unused, so only those still referred to are kept, numbered in
+    // the order they are first used. There are no more of them than a `u8` can index.
+    let mut out = Bytecode::default();
+    let mut renumbered = vec![None; constants.len()];
+    for (instruction, span) in instructions {
+        out.write_opcode(instruction.opcode, span);
+        if let Some(idx) = instruction.operand {
+            let idx = *renumbered[idx as usize]
+                .get_or_insert_with(|| out.add_constant(constants[idx as usize]) as u8);
+            out.write_byte(idx, span);
+        }
+    }
+
+    if bytecode.span(0).is_none() {
+        out.strip_spans();
+    }
+    out.set_max_stack_depth(verifier::max_stack_depth(&out).unwrap_or(bytecode.max_stack_depth()));
+
+    out
+}
+
+/// Rewrites the last instructions in `instructions` if they match a pattern, returning whether
+/// they did. Constants computed by a rewrite are added to `constants`, unless an equal one is
+/// already there.
+fn simplify(instructions: &mut Vec<(Instruction, Span)>, constants: &mut Vec<f64>) -> bool {
+    let [.., (first, first_span), (second, second_span)] = instructions[..] else {
+        return false;
+    };
+
+    let replacement = match (first.opcode, first.operand, second.opcode) {
+        (Opcode::Constant, _, Opcode::Pop) | (Opcode::Negate, _, Opcode::Negate) => None,
+        (Opcode::Constant, Some(idx), Opcode::Negate) => {
+            let Some(idx) = add_constant(constants, -constants[idx as usize]) else {
+                return false;
+            };
+
+            let instruction = Instruction {
+                opcode: Opcode::Constant,
+                operand: Some(idx),
+            };
+            Some((instruction, Span::cover(first_span, second_span)))
+        }
+        (Opcode::Constant, operand, opcode) => {
+            let opcode = match opcode {
+                Opcode::Add => Opcode::AddConstant,
+                Opcode::Subtract => Opcode::SubtractConstant,
+                Opcode::Multiply => Opcode::MultiplyConstant,
+                Opcode::Divide => Opcode::DivideConstant,
+                _ => return false,
+            };
+
+            Some((Instruction { opcode, operand }, second_span))
+        }
+        _ => return false,
+    };
+
+    instructions.truncate(instructions.len() - 2);
+    instructions.extend(replacement);
+    true
+}
+
+/// Returns the index of a constant with exactly the bits of `value`, adding one if there is none,
+/// or `None` if that would need an index that doesn't fit in an operand.
+fn add_constant(constants: &mut Vec<f64>, value: f64) -> Option<u8> {
+    if let Some(idx) = constants
+        .iter()
+        .position(|c| c.to_bits() == value.to_bits())
+    {
+        return idx.try_into().ok();
+    }
+
+    let idx = constants.len().try_into().ok()?;
+    constants.push(value);
+    Some(idx)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assembler::assemble, bytecode::Bytecode, vm::Vm};
+
+    use super::optimize;
+
+    fn listing(bytecode: &Bytecode) -> Vec<String> {
+        bytecode
+            .to_string()
+            .lines()
+            .map(|line| line.split(';').next().unwrap().trim().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn rewrites() {
+        let bytecode = assemble(
+            "Constant 1
+             Constant 2
+             Pop
+             Constant 3
+             Negate
+             Negate
+             Negate
+             Subtract
+             Constant 4
+             Negate
+             Add
+             Emit",
+        )
+        .unwrap();
+        let optimized = optimize(&bytecode);
+
+        assert_eq!(
+            listing(&optimized),
+            [
+                "0000  Constant         1",
+                "0002  SubtractConstant -3",
+                "0004  AddConstant      -4",
+                "0006  Emit",
+            ]
+        );
+        assert_eq!(optimized.max_stack_depth(), 1);
+        // Only the constants still used are kept, and negating a constant twice adds no new ones.
+        assert_eq!(optimized.constants(), [1.0, -3.0, -4.0]);
+        assert_eq!(
+            Vm::new(optimized).run().unwrap(),
+            Vm::new(bytecode).run().unwrap()
+        );
+    }
+}
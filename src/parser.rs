@@ -1,168 +1,400 @@
 use crate::{
-    ast::{Ast, BinOp, BinOpKind, Decl, Expr, ExprKind, Stmt, UnOp, UnOpKind},
-    diagnostics::report_error,
+    ast::{Ast, BinOp, BinOpKind, UnOp, UnOpKind},
+    diagnostics::{closest_name, Diagnostic, Label},
     lexer::{
         span::Span,
         token::{Token, TokenKind},
         Lexer, LexicalError,
     },
+    syntax::{lower, Builder, NodeKind, SyntaxNode},
 };
 
+/// How the end of a statement is marked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Every statement ends with `;`.
+    #[default]
+    Semicolons,
+    /// As with `Semicolons`, except that the last statement may leave out its `;`, so that a
+    /// single expression can be given on its own.
+    Expression,
+    /// A statement ends with `;` or at the end of its line, for scripts. Expressions continue onto
+    /// the next line after an operator, or inside parentheses.
+    Lines,
+}
+
 #[derive(Debug)]
 pub struct Parser<'a> {
     source: &'a str,
+    mode: Mode,
     lexer: Lexer<'a>,
     current: Token<'a>,
     previous: Token<'a>,
-    had_error: bool,
+    errors: Vec<ParseError>,
+    /// The number of expressions currently being parsed, each nested in the previous one.
+    depth: usize,
+    /// The number of parentheses opened but not yet closed.
+    parens: usize,
+    /// The syntax tree of the tokens consumed so far.
+    builder: Builder<'a>,
 }
 
 impl<'a> Parser<'a> {
+    /// The greatest depth to which expressions may be nested, beyond which parsing would risk
+    /// overflowing the stack.
+    pub const MAX_DEPTH: usize = 256;
+
     pub fn new(source: &'a str) -> Self {
         let mut parser = Self {
             source,
+            mode: Mode::default(),
             lexer: Lexer::new(source),
             current: Token::dummy(),
             previous: Token::dummy(),
-            had_error: false,
+            errors: vec![],
+            depth: 0,
+            parens: 0,
+            builder: Builder::new(source),
         };
 
         // For the parser to be in a valid state we need to advance here.
-        if parser.advance().is_err() {
-            parser.had_error = true;
-            parser.synchronize();
-        }
+        parser.advance();
 
         parser
     }
 
-    fn advance(&mut self) -> Result<(), LexicalError> {
-        let token = self.lexer.next_token()?;
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Errors encountered so far, in the order they were found. The parser doesn't report them
+    /// itself, leaving that to the caller.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    fn error(&mut self, err: ParseError) {
+        self.errors.push(err);
+    }
+
+    /// Moves on to the next token. A character that does not begin a token is reported, and
+    /// becomes an unknown token for the parser to recover from.
+    fn advance(&mut self) {
+        let token = self.lexer.next_token().unwrap_or_else(|err| {
+            let token = Token {
+                lexeme: err.span.slice(self.source),
+                span: err.span,
+                kind: TokenKind::Unknown,
+            };
+            self.error(err.into());
+            token
+        });
         self.previous = std::mem::replace(&mut self.current, token);
-        Ok(())
+        self.builder.token(self.previous.clone());
     }
 
-    fn expect(&mut self, kind: TokenKind) -> Result<(), ParseError> {
-        if self.current.kind == kind {
-            self.advance()?;
-            Ok(())
-        } else {
-            Err(ParseError::SyntacticError(SyntacticError {
-                span: self.current.span,
-                message: format!("expected {}, got {}", kind, self.current.kind),
-            }))
+    /// Returns the error for finding the current token in place of `kind`, which would close the
+    /// token at `opening` if there is one.
+    fn expected(&self, kind: TokenKind, opening: Option<Span>) -> ParseError {
+        SyntacticError {
+            span: self.current.span,
+            kind: SyntacticErrorKind::Expected {
+                expected: kind,
+                found: self.current.kind,
+                previous: self.previous.span,
+                opening,
+            },
         }
+        .into()
     }
 
+    /// Skips to the end of the statement in which parsing failed: past its `;`, or in `Lines`
+    /// mode up to the start of the next line.
     fn synchronize(&mut self) {
         while self.current.kind != TokenKind::Eof {
-            match self.current.kind {
-                TokenKind::Semicolon => {
-                    // If advance fails here need continue discarding until we reach the next
-                    // declaration.
-                    match self.advance() {
-                        Ok(()) => break,
-                        Err(err) => {
-                            // Report error.
-                            report_error("lexical error", err.span, self.source);
-                            continue;
-                        }
-                    }
-                }
-                // Tokens marking the begining of a declaration.
-                // TokenKind::Let | TokenKind::Func => break,
-                _ => {
-                    if let Err(err) = self.advance() {
-                        report_error("lexical error", err.span, self.source);
-                    }
-                    continue;
-                }
+            if self.current.kind == TokenKind::Semicolon {
+                self.advance();
+                break;
+            }
+
+            self.advance();
+            if self.mode == Mode::Lines && self.at_line_start() {
+                break;
             }
         }
     }
 
     pub fn parse(&mut self) -> Ast {
-        // Attempt to parse declarations, synchronize on failure.
-        let mut decls = vec![];
+        let root = self.parse_syntax();
+        Ast::new(lower(&root), self.errors.is_empty())
+    }
+
+    /// Parses the source into a lossless syntax tree, which retains every token, whitespace and
+    /// comment, and from which the `Ast` is lowered.
+    ///
+    /// Errors are recovered from as close to where they were found as possible, so that every
+    /// independent error is reported: a missing operand becomes an error node, an unclosed
+    /// parenthesis is closed at its statement's end, and a missing `;` ends the statement anyway.
+    pub fn parse_syntax(&mut self) -> SyntaxNode<'a> {
         while self.current.kind != TokenKind::Eof {
-            match self.decl() {
-                Ok(decl) => {
-                    decls.push(decl);
-                }
-                Err(err) => {
-                    self.had_error = true;
-                    match err {
-                        ParseError::LexicalError(err) => {
-                            report_error("lexical error", err.span, self.source);
-                        }
-                        ParseError::SyntacticError(err) => {
-                            report_error(&err.message, err.span, self.source);
-                        }
-                    }
-                    self.synchronize();
-                }
+            let checkpoint = self.builder.checkpoint(self.current.span);
+            let start = self.current.span;
+            if let Err(err) = self.decl() {
+                // Only nesting too deeply can't be recovered from within the statement.
+                self.error(err);
+                self.builder.start_node_at(checkpoint, NodeKind::Error);
+                self.synchronize();
+                self.builder.finish_node();
+            } else if self.current.span == start {
+                // No statement could begin with the current token, which has been reported.
+                self.builder.start_node(self.current.span, NodeKind::Error);
+                self.advance();
+                self.builder.finish_node();
             }
         }
 
-        Ast::new(decls, !self.had_error)
+        std::mem::replace(&mut self.builder, Builder::new(self.source)).finish()
+    }
+
+    fn decl(&mut self) -> Result<(), ParseError> {
+        self.stmt()
+    }
+
+    fn stmt(&mut self) -> Result<(), ParseError> {
+        // An unknown first token was reported when it was read, before the statement began.
+        let errors = self.errors.len() - usize::from(self.current.kind == TokenKind::Unknown);
+        self.builder.start_node(self.current.span, NodeKind::Stmt);
+        let result = self.expr(0);
+        if result.is_ok() {
+            self.end_stmt(self.errors.len() > errors);
+        }
+        self.builder.finish_node();
+        result
+    }
+
+    /// Consumes the `;` ending a statement, unless the mode allows it to be left out here. If it
+    /// is missing, the statement ends anyway so that the next one is still parsed. That is only
+    /// reported if the statement was otherwise well formed, as the next token is likely part of
+    /// the mistake already reported.
+    fn end_stmt(&mut self, had_error: bool) {
+        if self.current.kind == TokenKind::Semicolon {
+            self.advance();
+            return;
+        }
+
+        let at_end = self.current.kind == TokenKind::Eof;
+        let optional = match self.mode {
+            Mode::Semicolons => false,
+            Mode::Expression => at_end,
+            Mode::Lines => at_end || self.at_line_start(),
+        };
+        if optional {
+            return;
+        }
+
+        if self.current.kind == TokenKind::RParen {
+            // Parentheses closing nothing are skipped, so they don't also begin a statement.
+            self.error(self.expected(TokenKind::Semicolon, None));
+            self.builder.start_node(self.current.span, NodeKind::Error);
+            while self.current.kind == TokenKind::RParen {
+                self.advance();
+            }
+            self.builder.finish_node();
+            if self.current.kind == TokenKind::Semicolon {
+                self.advance();
+            }
+        } else if !had_error {
+            self.error(self.expected(TokenKind::Semicolon, None));
+        }
     }
 
-    fn decl(&mut self) -> Result<Decl, ParseError> {
-        Ok(Decl::stmt(self.stmt()?))
+    /// Whether the current token is the first on its line.
+    fn at_line_start(&self) -> bool {
+        self.source
+            .get(self.previous.span.end()..self.current.span.start())
+            .is_some_and(|between| between.contains('\n'))
     }
 
-    fn stmt(&mut self) -> Result<Stmt, ParseError> {
-        let expr = self.expr(0)?;
-        self.expect(TokenKind::Semicolon)?;
-        Ok(Stmt::expr(expr))
+    fn expr(&mut self, min_bp: u8) -> Result<(), ParseError> {
+        if self.depth >= Self::MAX_DEPTH {
+            return Err(SyntacticError {
+                span: self.current.span,
+                kind: SyntacticErrorKind::TooDeep,
+            }
+            .into());
+        }
+
+        self.depth += 1;
+        let result = self.expr_bp(min_bp);
+        self.depth -= 1;
+        result
     }
 
-    fn expr(&mut self, min_bp: u8) -> Result<Expr, ParseError> {
-        self.advance()?;
-        let mut expr = match self.previous.kind {
+    /// Parses an expression, wrapping each of its nodes in the syntax tree around the tokens they
+    /// consumed.
+    fn expr_bp(&mut self, min_bp: u8) -> Result<(), ParseError> {
+        let checkpoint = self.builder.checkpoint(self.current.span);
+        match self.current.kind {
             TokenKind::Number => {
-                let number = self.previous.lexeme.parse().unwrap();
-                Expr::new(self.previous.span, ExprKind::Number(number))
+                self.builder.start_node_at(checkpoint, NodeKind::Number);
+                self.advance();
+                self.builder.finish_node();
             }
             TokenKind::LParen => {
-                let expr = self.expr(0)?;
-                self.expect(TokenKind::RParen)?;
-                expr
+                self.builder.start_node_at(checkpoint, NodeKind::Paren);
+                self.advance();
+                let opening = self.previous.span;
+                self.parens += 1;
+                let errors = self.errors.len();
+                let result = self.expr(0);
+                if result.is_ok() {
+                    self.close_paren(opening, self.errors.len() > errors);
+                }
+                self.parens -= 1;
+                self.builder.finish_node();
+                result?;
+            }
+            TokenKind::Unknown | TokenKind::Identifier => {
+                self.builder.start_node_at(checkpoint, NodeKind::Error);
+                self.advance_reporting_name();
+                self.builder.finish_node();
             }
             _ => {
-                if let Some(op) = prefix_op(&self.previous) {
+                if let Some(op) = prefix_op(&self.current) {
                     let (_, r_bp) = prefix_binding_power(&op);
-                    Expr::unary(op, self.expr(r_bp)?)
+                    self.builder.start_node_at(checkpoint, NodeKind::Unary);
+                    self.advance();
+                    let result = self.expr(r_bp);
+                    self.builder.finish_node();
+                    result?;
                 } else {
-                    return Err(SyntacticError {
-                        span: self.current.span,
-                        message: "expected expression".to_owned(),
-                    }
-                    .into());
+                    self.missing_expr();
                 }
             }
-        };
+        }
 
         loop {
-            if let Some(op) = infix_op(&self.current) {
-                let (l_bp, r_bp) = infix_binding_power(&op);
-                if l_bp < min_bp {
-                    break;
-                }
+            // An operator beginning a line begins a new statement, rather than continuing this one.
+            if self.mode == Mode::Lines && self.parens == 0 && self.at_line_start() {
+                break;
+            }
 
-                self.advance()?;
-                expr = Expr::binary(op, expr, self.expr(r_bp)?);
-                continue;
+            // An unknown character or name between two operands is taken to be a mistyped operator.
+            let (kind, (l_bp, r_bp)) = if let Some(op) = infix_op(&self.current) {
+                (NodeKind::Binary, infix_binding_power(&op))
+            } else if matches!(
+                self.current.kind,
+                TokenKind::Unknown | TokenKind::Identifier
+            ) {
+                (NodeKind::Error, UNKNOWN_BINDING_POWER)
+            } else {
+                break;
+            };
+            if l_bp < min_bp {
+                break;
             }
 
-            break;
+            self.builder.start_node_at(checkpoint, kind);
+            self.advance_reporting_name();
+            let result = self.expr(r_bp);
+            self.builder.finish_node();
+            result?;
         }
 
-        Ok(expr)
+        Ok(())
+    }
+
+    /// Moves on to the next token, first reporting the current one if it is a name. Names are
+    /// reported as they are consumed, so that one skipped in recovering from an error isn't.
+    fn advance_reporting_name(&mut self) {
+        if self.current.kind == TokenKind::Identifier {
+            self.error(
+                SyntacticError {
+                    span: self.current.span,
+                    kind: SyntacticErrorKind::UnknownName,
+                }
+                .into(),
+            );
+        }
+        self.advance();
+    }
+
+    /// Reports that the current token can't begin an expression, and adds an error node in place
+    /// of the missing one. The token is left for the caller to recover at, unless it is a `)`
+    /// closing nothing, which is skipped along with any that follow it.
+    fn missing_expr(&mut self) {
+        // After an unknown character or name taken as an operator, it has been reported.
+        if !matches!(
+            self.previous.kind,
+            TokenKind::Unknown | TokenKind::Identifier
+        ) {
+            self.error(
+                SyntacticError {
+                    span: self.current.span,
+                    kind: SyntacticErrorKind::ExpectedExpression {
+                        found: self.current.kind,
+                    },
+                }
+                .into(),
+            );
+        }
+
+        self.builder.start_node(self.current.span, NodeKind::Error);
+        while self.current.kind == TokenKind::RParen && self.parens == 0 {
+            self.advance();
+        }
+        self.builder.finish_node();
+    }
+
+    /// Consumes the `)` closing the parenthesis at `opening`. If another token is found instead,
+    /// the rest of the parenthesized expression is skipped up to its `)`, unless its statement
+    /// ends first. As with `;`, that is only reported if the expression was otherwise well formed.
+    fn close_paren(&mut self, opening: Span, had_error: bool) {
+        if self.current.kind == TokenKind::RParen {
+            self.advance();
+            return;
+        }
+
+        if !had_error {
+            self.error(self.expected(TokenKind::RParen, Some(opening)));
+        }
+        let ends_stmt = |parser: &Self| match parser.current.kind {
+            TokenKind::Semicolon | TokenKind::Eof => true,
+            _ => parser.mode == Mode::Lines && parser.at_line_start(),
+        };
+        if ends_stmt(self) {
+            return;
+        }
+
+        self.builder.start_node(self.current.span, NodeKind::Error);
+        let mut depth = 0;
+        while !ends_stmt(self) {
+            match self.current.kind {
+                TokenKind::LParen => depth += 1,
+                TokenKind::RParen if depth == 0 => break,
+                TokenKind::RParen => depth -= 1,
+                _ => {}
+            }
+            self.advance();
+        }
+        self.builder.finish_node();
+
+        if self.current.kind == TokenKind::RParen {
+            self.advance();
+        }
     }
 }
 
-fn prefix_op(token: &Token) -> Option<UnOp> {
+/// The binding power of an unknown character or name taken as an operator, which is the loosest of
+/// any.
+const UNKNOWN_BINDING_POWER: (u8, u8) = (1, 2);
+
+/// The names the language defines, against which unknown names are compared to suggest what was
+/// meant. There are no built-ins yet, so nothing is suggested.
+const BUILTINS: &[&str] = &[];
+
+pub(crate) fn prefix_op(token: &Token) -> Option<UnOp> {
     let unop = match token.kind {
         TokenKind::Minus => UnOp::new(token.span, UnOpKind::Neg),
         _ => return None,
@@ -171,7 +403,7 @@ fn prefix_op(token: &Token) -> Option<UnOp> {
     Some(unop)
 }
 
-fn infix_op(token: &Token) -> Option<BinOp> {
+pub(crate) fn infix_op(token: &Token) -> Option<BinOp> {
     let kind = match token.kind {
         TokenKind::Plus => BinOpKind::Add,
         TokenKind::Minus => BinOpKind::Sub,
@@ -184,24 +416,55 @@ fn infix_op(token: &Token) -> Option<BinOp> {
     Some(BinOp::new(token.span, kind))
 }
 
-fn prefix_binding_power(unop: &UnOp) -> ((), u8) {
+pub(crate) fn prefix_binding_power(unop: &UnOp) -> ((), u8) {
     match unop.kind() {
         UnOpKind::Neg => ((), 5),
     }
 }
 
-fn infix_binding_power(binop: &BinOp) -> (u8, u8) {
+pub(crate) fn infix_binding_power(binop: &BinOp) -> (u8, u8) {
     match binop.kind() {
         BinOpKind::Add | BinOpKind::Sub => (1, 2),
         BinOpKind::Mul | BinOpKind::Div | BinOpKind::Rem => (3, 4),
     }
 }
 
+#[derive(Debug)]
 pub enum ParseError {
     LexicalError(LexicalError),
     SyntacticError(SyntacticError),
 }
 
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::LexicalError(err) => err.span,
+            ParseError::SyntacticError(err) => err.span,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::LexicalError(err) => err.message().to_owned(),
+            ParseError::SyntacticError(err) => err.message(),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::LexicalError(err) => err.code(),
+            ParseError::SyntacticError(err) => err.code(),
+        }
+    }
+
+    pub fn diagnostic(&self, source: &str) -> Diagnostic {
+        match self {
+            ParseError::LexicalError(err) => err.diagnostic(source),
+            ParseError::SyntacticError(err) => err.diagnostic(source),
+        }
+    }
+}
+
 impl From<LexicalError> for ParseError {
     fn from(value: LexicalError) -> Self {
         ParseError::LexicalError(value)
@@ -217,5 +480,221 @@ impl From<SyntacticError> for ParseError {
 #[derive(Debug, Clone)]
 pub struct SyntacticError {
     pub span: Span,
-    pub message: String,
+    pub kind: SyntacticErrorKind,
+}
+
+impl SyntacticError {
+    pub fn message(&self) -> String {
+        match self.kind {
+            SyntacticErrorKind::Expected {
+                expected, found, ..
+            } => format!("expected {}, found {}", expected, found),
+            SyntacticErrorKind::ExpectedExpression { found } => {
+                format!("expected expression, found {}", found)
+            }
+            SyntacticErrorKind::TooDeep => "expression nested too deeply".to_owned(),
+            SyntacticErrorKind::UnknownName => "unknown name".to_owned(),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn diagnostic(&self, source: &str) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.message()).with_code(self.code());
+        match self.kind {
+            SyntacticErrorKind::Expected {
+                expected,
+                found,
+                previous,
+                opening,
+            } => {
+                let diagnostic = diagnostic
+                    .with_label(Label::primary(self.span, format!("expected {}", expected)));
+                let diagnostic = match opening {
+                    Some(opening) => {
+                        diagnostic.with_label(Label::secondary(opening, "opening parenthesis here"))
+                    }
+                    None => diagnostic,
+                };
+                let insert = Span::new(previous.end(), previous.end());
+                match (expected, found) {
+                    (TokenKind::Semicolon, TokenKind::RParen) => diagnostic.with_suggestion(
+                        self.span,
+                        "",
+                        "remove the unmatched parenthesis",
+                    ),
+                    (TokenKind::Semicolon, _) => diagnostic.with_suggestion(
+                        insert,
+                        ";",
+                        "end the statement with a semicolon",
+                    ),
+                    (TokenKind::RParen, _) => {
+                        diagnostic.with_suggestion(insert, ")", "close the parenthesis")
+                    }
+                    _ => diagnostic,
+                }
+            }
+            SyntacticErrorKind::ExpectedExpression { .. } => diagnostic
+                .with_label(Label::primary(self.span, "expected expression"))
+                .with_note("expressions start with a number, '(' or '-'"),
+            SyntacticErrorKind::TooDeep => diagnostic
+                .with_label(Label::primary(self.span, "nested too deeply"))
+                .with_note(format!(
+                    "expressions may be nested at most {} deep",
+                    Parser::MAX_DEPTH
+                )),
+            SyntacticErrorKind::UnknownName => {
+                let name = self.span.slice(source);
+                let diagnostic = diagnostic.with_label(Label::primary(
+                    self.span,
+                    format!("`{}` is not defined", name),
+                ));
+                // The digits after an `x` are lexed as part of the name, as in `2x3`.
+                let x = Span::new(self.span.start(), self.span.start() + 1);
+                let is_times = name.starts_with(['x', 'X'])
+                    && name[1..].bytes().all(|b| b.is_ascii_digit())
+                    && is_between_operands(source, x);
+                if is_times {
+                    diagnostic.with_suggestion(x, "*", "use `*` to multiply")
+                } else if let Some(builtin) = closest_name(name, BUILTINS) {
+                    diagnostic.with_suggestion(
+                        self.span,
+                        builtin,
+                        format!("did you mean `{}`?", builtin),
+                    )
+                } else {
+                    diagnostic
+                }
+            }
+        }
+    }
+}
+
+/// Whether `span` is between what look like the operands of a binary operator, such as the `x` in
+/// `2 x (3)`.
+fn is_between_operands(source: &str, span: Span) -> bool {
+    let before = source[..span.start()].trim_end().chars().next_back();
+    let after = source[span.end()..].trim_start().chars().next();
+    matches!(before, Some('0'..='9' | ')')) && matches!(after, Some('0'..='9' | '(' | '-'))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SyntacticErrorKind {
+    /// A token other than `expected` was found after the token at `previous`. If it was to close
+    /// a parenthesis, `opening` is the span of the `(`.
+    Expected {
+        expected: TokenKind,
+        found: TokenKind,
+        previous: Span,
+        opening: Option<Span>,
+    },
+    ExpectedExpression {
+        found: TokenKind,
+    },
+    TooDeep,
+    /// A name that the language does not define.
+    UnknownName,
+}
+
+impl SyntacticErrorKind {
+    /// The stable code identifying the error, which `calculator --explain` describes.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SyntacticErrorKind::ExpectedExpression { .. } => "E0100",
+            SyntacticErrorKind::Expected { .. } => "E0101",
+            SyntacticErrorKind::TooDeep => "E0102",
+            SyntacticErrorKind::UnknownName => "E0103",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{diagnostics::apply_suggestions, printer::print};
+
+    use super::{Mode, Parser};
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth| format!("{}1{};", "(".repeat(depth), ")".repeat(depth));
+        assert!(Parser::new(&nested(Parser::MAX_DEPTH - 1))
+            .parse()
+            .complete());
+
+        let source = nested(Parser::MAX_DEPTH);
+        let mut parser = Parser::new(&source);
+        assert!(!parser.parse().complete());
+        assert_eq!(parser.errors().len(), 1);
+
+        let source = format!("{}1;", "-".repeat(100_000));
+        assert!(!Parser::new(&source).parse().complete());
+    }
+
+    #[test]
+    fn suggestions() {
+        let fix = |source: &str| {
+            let mut parser = Parser::new(source);
+            parser.parse();
+            let suggestions: Vec<_> = parser
+                .errors()
+                .iter()
+                .flat_map(|err| err.diagnostic(source).suggestions().to_vec())
+                .collect();
+            apply_suggestions(source, &suggestions).0
+        };
+
+        assert_eq!(fix("(1 + 2 // sum"), "(1 + 2) // sum");
+        assert_eq!(fix("1 + 2\n"), "1 + 2;\n");
+        assert_eq!(fix("2 x 3 × (4 ÷ 5);"), "2 * 3 * (4 / 5);");
+        assert_eq!(fix("x 1;"), "x 1;");
+        assert_eq!(fix("2x3; 2 xy 3;"), "2*3; 2 xy 3;");
+        assert_eq!(fix("1 + 2); 3;"), "1 + 2; 3;");
+    }
+
+    #[test]
+    fn recovery() {
+        let codes = |source| {
+            let mut parser = Parser::new(source);
+            let ast = parser.parse();
+            assert!(!ast.complete());
+            let codes: Vec<_> = parser.errors().iter().map(|err| err.code()).collect();
+            (codes, print(&ast))
+        };
+
+        let (errors, printed) = codes("1 +; 2 3; (4 5); # 6; 7;");
+        assert_eq!(errors, ["E0100", "E0101", "E0101", "E0001"]);
+        assert_eq!(printed, "1 + <error>;\n2;\n3;\n4;\n<error>;\n6;\n7;\n");
+
+        let (errors, printed) = codes("(1 + ; 2)); 3 * ;");
+        assert_eq!(errors, ["E0100", "E0101", "E0100"]);
+        assert_eq!(printed, "1 + <error>;\n2;\n3 * <error>;\n");
+
+        let (errors, _) = codes("1 # 2; 3 + 4");
+        assert_eq!(errors, ["E0001", "E0101"]);
+
+        // A name is reported once, however many letters it has.
+        let (errors, printed) = codes("sqrt(4); 2 x 3;");
+        assert_eq!(errors, ["E0103", "E0103"]);
+        assert_eq!(printed, "<error>;\n4;\n<error>;\n");
+    }
+
+    #[test]
+    fn modes() {
+        let parse = |source, mode| {
+            let mut parser = Parser::new(source).with_mode(mode);
+            let ast = parser.parse();
+            parser.errors().is_empty().then(|| print(&ast))
+        };
+
+        assert_eq!(parse("1 + 2", Mode::Semicolons), None);
+        assert_eq!(parse("1; 2", Mode::Expression).as_deref(), Some("1;\n2;\n"));
+        assert_eq!(parse("1\n2", Mode::Expression), None);
+        assert_eq!(
+            parse("1 +\n2\n-3; 4 // c\n(5\n* 6)", Mode::Lines).as_deref(),
+            Some("1 + 2;\n-3;\n4;\n5 * 6;\n")
+        );
+        assert_eq!(parse("1 2", Mode::Lines), None);
+    }
 }
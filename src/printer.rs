@@ -0,0 +1,120 @@
+//! Prints an `Ast` back to source code.
+//!
+//! Parentheses are only written where they are needed for the source to parse back to the same
+//! tree, which is decided with the same binding powers the parser uses. Numbers the parser cannot
+//! produce, such as those created by constant folding, are written as expressions evaluating to
+//! them: negative numbers as negations, infinities as division by zero, and NaN as `0 / 0`.
+
+use crate::{
+    ast::{Ast, BinOp, BinOpKind, DeclKind, Expr, ExprKind, StmtKind, UnOp, UnOpKind},
+    lexer::span::Span,
+    parser::{infix_binding_power, prefix_binding_power},
+};
+
+/// Prints each statement in `ast` on its own line.
+pub fn print(ast: &Ast) -> String {
+    let mut out = String::new();
+    for decl in ast.decls() {
+        match decl.kind() {
+            DeclKind::Stmt(stmt) => match stmt.kind() {
+                StmtKind::Expr(expr) => {
+                    out.push_str(&print_expr(expr));
+                    out.push_str(";\n");
+                }
+            },
+        }
+    }
+
+    out
+}
+
+pub fn print_expr(expr: &Expr) -> String {
+    let mut out = String::new();
+    write_expr(&mut out, expr);
+    out
+}
+
+/// Where an expression appears in its parent, which decides whether it needs parentheses.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Position<'a> {
+    Left(&'a BinOp),
+    Right(&'a BinOp),
+    Operand(&'a UnOp),
+}
+
+/// Returns whether `expr` must be parenthesized to parse back as a single operand at `position`.
+pub(crate) fn needs_parens(expr: &Expr, position: Position) -> bool {
+    let (l_bp, r_bp) = match expr.kind() {
+        ExprKind::Binary(op, ..) => infix_binding_power(op),
+        ExprKind::Number(value) if value.is_nan() || value.is_infinite() => {
+            infix_binding_power(&BinOp::new(Span::new(0, 0), BinOpKind::Div))
+        }
+        _ => return false,
+    };
+
+    match position {
+        // The parent would be absorbed into the right operand of the left child if it binds at
+        // least as tightly, and the right child is only parsed as a whole if it binds at least
+        // as tightly as the parent's right binding power.
+        Position::Left(op) => r_bp <= infix_binding_power(op).0,
+        Position::Right(op) => l_bp < infix_binding_power(op).1,
+        Position::Operand(op) => l_bp < prefix_binding_power(op).1,
+    }
+}
+
+fn write_expr(out: &mut String, expr: &Expr) {
+    match expr.kind() {
+        ExprKind::Number(value) => write_number(out, *value),
+        ExprKind::Error => out.push_str("<error>"),
+        ExprKind::Unary(op, operand) => {
+            out.push_str(unop_str(op));
+            write_operand(out, operand, Position::Operand(op));
+        }
+        ExprKind::Binary(op, operand_1, operand_2) => {
+            write_operand(out, operand_1, Position::Left(op));
+            out.push(' ');
+            out.push_str(binop_str(op));
+            out.push(' ');
+            write_operand(out, operand_2, Position::Right(op));
+        }
+    }
+}
+
+fn write_operand(out: &mut String, operand: &Expr, position: Position) {
+    if needs_parens(operand, position) {
+        out.push('(');
+        write_expr(out, operand);
+        out.push(')');
+    } else {
+        write_expr(out, operand);
+    }
+}
+
+fn write_number(out: &mut String, value: f64) {
+    if value.is_nan() {
+        out.push_str("0 / 0");
+    } else if value.is_infinite() {
+        out.push_str(if value > 0.0 { "1 / 0" } else { "-1 / 0" });
+    } else if value.is_sign_negative() {
+        out.push('-');
+        out.push_str(&(-value).to_string());
+    } else {
+        out.push_str(&value.to_string());
+    }
+}
+
+pub(crate) fn unop_str(op: &UnOp) -> &'static str {
+    match op.kind() {
+        UnOpKind::Neg => "-",
+    }
+}
+
+pub(crate) fn binop_str(op: &BinOp) -> &'static str {
+    match op.kind() {
+        BinOpKind::Add => "+",
+        BinOpKind::Sub => "-",
+        BinOpKind::Mul => "*",
+        BinOpKind::Div => "/",
+        BinOpKind::Rem => "%",
+    }
+}
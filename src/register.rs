@@ -0,0 +1,103 @@
+//! A register-based alternative to the stack `Vm`.
+//!
+//! Instructions are three-address: each reads its operands from registers and writes its result
+//! to a register, rather than popping and pushing a stack. Numbers are stored inline in the
+//! instruction that loads them instead of in a constant table. Register numbers are assigned in
+//! the order subexpressions are evaluated, so a `Program` uses as many registers as the stack `Vm`
+//! would need stack slots for the same `Ast`.
+
+pub mod codegen;
+pub mod vm;
+
+use std::fmt::Display;
+
+pub type Register = u16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Load {
+        dst: Register,
+        value: f64,
+    },
+    Negate {
+        dst: Register,
+        src: Register,
+    },
+    Add {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    Subtract {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    Multiply {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    Divide {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    Remainder {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    /// Reports the value of the register as the result of a top-level expression statement.
+    Emit {
+        src: Register,
+    },
+}
+
+/// Register code for a whole `Ast`. Only produced by the register `CodeGenerator`, so every
+/// register an instruction refers to is less than `register_count`.
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    register_count: usize,
+}
+
+impl Program {
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn register_count(&self) -> usize {
+        self.register_count
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, instruction) in self.instructions.iter().enumerate() {
+            write!(f, "{:04}  ", idx)?;
+            match *instruction {
+                Instruction::Load { dst, value } => writeln!(f, "Load      r{}, {}", dst, value)?,
+                Instruction::Negate { dst, src } => writeln!(f, "Negate    r{}, r{}", dst, src)?,
+                Instruction::Add { dst, lhs, rhs } => {
+                    writeln!(f, "Add       r{}, r{}, r{}", dst, lhs, rhs)?
+                }
+                Instruction::Subtract { dst, lhs, rhs } => {
+                    writeln!(f, "Subtract  r{}, r{}, r{}", dst, lhs, rhs)?
+                }
+                Instruction::Multiply { dst, lhs, rhs } => {
+                    writeln!(f, "Multiply  r{}, r{}, r{}", dst, lhs, rhs)?
+                }
+                Instruction::Divide { dst, lhs, rhs } => {
+                    writeln!(f, "Divide    r{}, r{}, r{}", dst, lhs, rhs)?
+                }
+                Instruction::Remainder { dst, lhs, rhs } => {
+                    writeln!(f, "Remainder r{}, r{}, r{}", dst, lhs, rhs)?
+                }
+                Instruction::Emit { src } => writeln!(f, "Emit      r{}", src)?,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{
+    ast::{Ast, BinOpKind, Decl, DeclKind, Expr, ExprKind, Stmt, StmtKind, UnOpKind},
+    codegen::CodegenError,
+    lexer::span::Span,
+};
+
+use super::{Instruction, Program, Register};
+
+#[derive(Debug, Default)]
+pub struct CodeGenerator {
+    program: Program,
+    /// The lowest numbered register not holding the value of a pending subexpression.
+    next_register: usize,
+    /// The first error found, after which code is still generated but then discarded.
+    error: Option<CodegenError>,
+}
+
+impl CodeGenerator {
+    pub fn generate(&mut self, ast: &Ast) -> Result<Program, CodegenError> {
+        for decl in ast.decls() {
+            self.decl(decl);
+        }
+
+        self.next_register = 0;
+        let program = std::mem::take(&mut self.program);
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(program),
+        }
+    }
+
+    fn error(&mut self, err: CodegenError) {
+        self.error.get_or_insert(err);
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        match decl.kind() {
+            DeclKind::Stmt(stmt) => self.stmt(stmt),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt.kind() {
+            StmtKind::Expr(expr) => {
+                let src = self.expr(expr);
+                self.program.instructions.push(Instruction::Emit { src });
+                self.next_register -= 1;
+            }
+        }
+    }
+
+    /// Generates code leaving the value of `expr` in the returned register, which is the lowest
+    /// free register at the time of the call.
+    fn expr(&mut self, expr: &Expr) -> Register {
+        match expr.kind() {
+            ExprKind::Number(value) => {
+                let dst = self.allocate(expr.span());
+                self.program
+                    .instructions
+                    .push(Instruction::Load { dst, value: *value });
+                dst
+            }
+            // A register is still allocated so that every error is found.
+            ExprKind::Error => {
+                self.error(CodegenError::Incomplete { span: expr.span() });
+                let dst = self.allocate(expr.span());
+                self.program.instructions.push(Instruction::Load {
+                    dst,
+                    value: f64::NAN,
+                });
+                dst
+            }
+            ExprKind::Unary(op, expr) => {
+                let dst = self.expr(expr);
+                self.program.instructions.push(match op.kind() {
+                    UnOpKind::Neg => Instruction::Negate { dst, src: dst },
+                });
+                dst
+            }
+            ExprKind::Binary(op, expr_l, expr_r) => {
+                let lhs = self.expr(expr_l);
+                let rhs = self.expr(expr_r);
+                let dst = lhs;
+                self.program.instructions.push(match op.kind() {
+                    BinOpKind::Add => Instruction::Add { dst, lhs, rhs },
+                    BinOpKind::Sub => Instruction::Subtract { dst, lhs, rhs },
+                    BinOpKind::Mul => Instruction::Multiply { dst, lhs, rhs },
+                    BinOpKind::Div => Instruction::Divide { dst, lhs, rhs },
+                    BinOpKind::Rem => Instruction::Remainder { dst, lhs, rhs },
+                });
+                self.next_register -= 1;
+                dst
+            }
+        }
+    }
+
+    /// Allocates the register holding the value of the expression at `span`.
+    fn allocate(&mut self, span: Span) -> Register {
+        let register = self.next_register;
+        self.next_register += 1;
+        self.program.register_count = self.program.register_count.max(self.next_register);
+        register.try_into().unwrap_or_else(|_| {
+            self.error(CodegenError::TooManyRegisters { span });
+            Register::MAX
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ast::{Ast, BinOp, BinOpKind, Decl, Expr, ExprKind, Stmt},
+        codegen::CodegenError,
+        lexer::span::Span,
+    };
+
+    use super::CodeGenerator;
+
+    #[test]
+    fn too_many_registers() {
+        // Generating code for, and dropping, the deeply nested tree recurses once per level.
+        let generate = |depth| {
+            std::thread::Builder::new()
+                .stack_size(1 << 28)
+                .spawn(move || {
+                    let number = |i| Expr::new(Span::new(i, i + 1), ExprKind::Number(1.0));
+                    let op = BinOp::new(Span::new(0, 0), BinOpKind::Add);
+                    let mut expr = number(depth);
+                    for i in (0..depth).rev() {
+                        expr = Expr::binary(op, number(i), expr);
+                    }
+                    let ast = Ast::new(vec![Decl::stmt(Stmt::expr(expr))], true);
+                    CodeGenerator::default().generate(&ast).map(|_| ())
+                })
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+
+        // Each operand on the left holds a register while the rest of the sum is evaluated.
+        assert_eq!(generate(CodegenError::MAX_REGISTERS - 1), Ok(()));
+        assert_eq!(
+            generate(CodegenError::MAX_REGISTERS),
+            Err(CodegenError::TooManyRegisters {
+                span: Span::new(CodegenError::MAX_REGISTERS, CodegenError::MAX_REGISTERS + 1)
+            })
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::Instant;
+
+use crate::vm::{self, VmError};
+
+use super::{Instruction, Program};
+
+pub struct Vm {
+    program: Program,
+    registers: Vec<f64>,
+    /// The number of instructions left to execute, or `None` if there is no limit.
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    /// The greatest number of bytes the program may hold at once, or `None` if there is no limit.
+    memory_limit: Option<usize>,
+}
+
+impl Vm {
+    pub fn new(program: Program) -> Self {
+        Self {
+            registers: vec![0.0; program.register_count()],
+            program,
+            fuel: None,
+            deadline: None,
+            memory_limit: None,
+        }
+    }
+
+    /// Limits the number of instructions `run` may execute before failing with
+    /// [`VmError::BudgetExhausted`].
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Sets a time after which `run` fails with [`VmError::BudgetExhausted`].
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Limits the number of bytes of values the program may hold at once, counting both its
+    /// registers and the values it has emitted, beyond which running the program fails with
+    /// [`VmError::OutOfMemory`].
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Runs the program to completion, yielding the values of its top-level expression statements
+    /// in the order they were evaluated.
+    ///
+    /// The limits are those of the stack `Vm`, but a program that exceeds its budget can't be
+    /// resumed, and register code has no spans for errors to point at.
+    pub fn run(&mut self) -> Result<Vec<f64>, VmError> {
+        let held = |values: usize| (self.registers.len() + values) * size_of::<f64>();
+        if self.memory_limit.is_some_and(|limit| held(0) > limit) {
+            return Err(VmError::OutOfMemory { span: None });
+        }
+
+        let mut values = vec![];
+        let registers = &mut self.registers;
+        for (executed, instruction) in self.program.instructions.iter().enumerate() {
+            let past_deadline = self.deadline.is_some_and(|deadline| {
+                (executed as u64).is_multiple_of(vm::Vm::DEADLINE_CHECK_INTERVAL)
+                    && Instant::now() >= deadline
+            });
+            if self.fuel == Some(0) || past_deadline {
+                return Err(VmError::BudgetExhausted { span: None });
+            }
+            if let Some(fuel) = &mut self.fuel {
+                *fuel -= 1;
+            }
+
+            match *instruction {
+                Instruction::Load { dst, value } => registers[dst as usize] = value,
+                Instruction::Negate { dst, src } => {
+                    registers[dst as usize] = -registers[src as usize]
+                }
+                Instruction::Add { dst, lhs, rhs } => {
+                    registers[dst as usize] = registers[lhs as usize] + registers[rhs as usize]
+                }
+                Instruction::Subtract { dst, lhs, rhs } => {
+                    registers[dst as usize] = registers[lhs as usize] - registers[rhs as usize]
+                }
+                Instruction::Multiply { dst, lhs, rhs } => {
+                    registers[dst as usize] = registers[lhs as usize] * registers[rhs as usize]
+                }
+                Instruction::Divide { dst, lhs, rhs } => {
+                    registers[dst as usize] = registers[lhs as usize] / registers[rhs as usize]
+                }
+                Instruction::Remainder { dst, lhs, rhs } => {
+                    registers[dst as usize] = registers[lhs as usize] % registers[rhs as usize]
+                }
+                Instruction::Emit { src } => {
+                    let held = (registers.len() + values.len() + 1) * size_of::<f64>();
+                    if self.memory_limit.is_some_and(|limit| held > limit) {
+                        return Err(VmError::OutOfMemory { span: None });
+                    }
+                    values.push(registers[src as usize]);
+                }
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        codegen,
+        parser::Parser,
+        register::codegen::CodeGenerator,
+        vm::{self, VmError},
+    };
+
+    use super::Vm;
+
+    #[test]
+    fn matches_stack_vm() {
+        for source in [
+            "1 + 2 * 3; -(4 - 5) / 6;",
+            "1 + (2 + (3 + (4 + 5)));",
+            "0 / 0; -0; 1 / -0; -7 % 3; 0.1 + 0.2;",
+        ] {
+            let ast = Parser::new(source).parse();
+            let expected = vm::Vm::new(codegen::CodeGenerator::default().generate(&ast).unwrap())
+                .run()
+                .unwrap();
+            let program = CodeGenerator::default().generate(&ast).unwrap();
+            let actual = Vm::new(program).run().unwrap();
+
+            let bits = |values: Vec<f64>| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(actual), bits(expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let program = |source| {
+            CodeGenerator::default()
+                .generate(&Parser::new(source).parse())
+                .unwrap()
+        };
+
+        // `1 + 2;` loads two registers, adds them and emits the sum.
+        assert_eq!(
+            Vm::new(program("1 + 2;")).with_fuel(4).run().unwrap(),
+            vec![3.0]
+        );
+        assert!(matches!(
+            Vm::new(program("1 + 2;")).with_fuel(3).run(),
+            Err(VmError::BudgetExhausted { span: None })
+        ));
+
+        assert_eq!(
+            Vm::new(program("1 + 2;"))
+                .with_memory_limit(24)
+                .run()
+                .unwrap(),
+            vec![3.0]
+        );
+        assert!(matches!(
+            Vm::new(program("1 + 2;")).with_memory_limit(8).run(),
+            Err(VmError::OutOfMemory { span: None })
+        ));
+        // Emitted values are held until the program finishes.
+        assert!(matches!(
+            Vm::new(program("1 + 2;")).with_memory_limit(16).run(),
+            Err(VmError::OutOfMemory { span: None })
+        ));
+    }
+}
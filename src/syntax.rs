@@ -0,0 +1,288 @@
+//! A lossless concrete syntax tree.
+//!
+//! Unlike the `Ast`, the tree retains every token of the source, including parentheses, whitespace,
+//! comments, characters that failed to lex, and tokens skipped while recovering from syntax errors,
+//! so printing it reproduces the source exactly. Whitespace and comments between the tokens of a
+//! node are its children, while those before a node belong to its parent.
+
+mod lower;
+
+pub use self::lower::lower;
+
+use std::fmt::{Display, Formatter, Write};
+
+use crate::lexer::{
+    span::Span,
+    token::{Token, TokenKind},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The whole source.
+    Root,
+    /// An expression followed by a semicolon.
+    Stmt,
+    Number,
+    /// A parenthesized expression.
+    Paren,
+    Unary,
+    Binary,
+    /// Tokens skipped while recovering from a syntax error, along with any partially parsed
+    /// statement before them.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement<'a> {
+    Node(SyntaxNode<'a>),
+    Token(Token<'a>),
+}
+
+impl SyntaxElement<'_> {
+    pub fn span(&self) -> Span {
+        match self {
+            SyntaxElement::Node(node) => node.span,
+            SyntaxElement::Token(token) => token.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode<'a> {
+    kind: NodeKind,
+    span: Span,
+    children: Vec<SyntaxElement<'a>>,
+}
+
+impl<'a> SyntaxNode<'a> {
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn children(&self) -> &[SyntaxElement<'a>] {
+        &self.children
+    }
+
+    /// The child nodes, skipping over tokens.
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// The child tokens that are not trivia.
+    pub fn tokens(&self) -> impl Iterator<Item = &Token<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Token(token) if !token.kind.is_trivia() => Some(token),
+            _ => None,
+        })
+    }
+
+    /// Returns an indented tree of every node and token along with its span.
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        self.dump_node(&mut out, 0);
+        out
+    }
+
+    fn dump_node(&self, out: &mut String, depth: usize) {
+        writeln!(
+            out,
+            "{:indent$}{:?} {}",
+            "",
+            self.kind,
+            self.span,
+            indent = depth * 2
+        )
+        .expect("writing to a string cannot fail");
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.dump_node(out, depth + 1),
+                SyntaxElement::Token(token) => writeln!(
+                    out,
+                    "{:indent$}{:?} {:?} {}",
+                    "",
+                    token.kind,
+                    token.lexeme,
+                    token.span,
+                    indent = (depth + 1) * 2
+                )
+                .expect("writing to a string cannot fail"),
+            }
+        }
+    }
+}
+
+impl Display for SyntaxNode<'_> {
+    /// Writes the source the node was parsed from.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.fmt(f)?,
+                SyntaxElement::Token(token) => f.write_str(token.lexeme)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The position in the tree at which a node can later be started, so that it contains everything
+/// added since.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Checkpoint(usize);
+
+/// Assembles a `SyntaxNode` from the tokens consumed by the parser, filling in the trivia between
+/// them from the source.
+#[derive(Debug)]
+pub(crate) struct Builder<'a> {
+    source: &'a str,
+    /// The end of the source added to the tree so far.
+    offset: usize,
+    /// The nodes started but not yet finished, with the index in `children` of their first child.
+    parents: Vec<(NodeKind, usize)>,
+    children: Vec<SyntaxElement<'a>>,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            offset: 0,
+            parents: vec![],
+            children: vec![],
+        }
+    }
+
+    /// Returns a checkpoint before `next`, the next token to be added, so that trivia before it
+    /// is left outside any node started at the checkpoint.
+    pub fn checkpoint(&mut self, next: Span) -> Checkpoint {
+        self.trivia(next.start());
+        Checkpoint(self.children.len())
+    }
+
+    pub fn start_node(&mut self, next: Span, kind: NodeKind) {
+        let checkpoint = self.checkpoint(next);
+        self.start_node_at(checkpoint, kind);
+    }
+
+    pub fn start_node_at(&mut self, checkpoint: Checkpoint, kind: NodeKind) {
+        self.parents.push((kind, checkpoint.0));
+    }
+
+    pub fn finish_node(&mut self) {
+        let (kind, first) = self.parents.pop().expect("no node to finish");
+        let children: Vec<_> = self.children.drain(first..).collect();
+        self.children
+            .push(SyntaxElement::Node(self.node(kind, children)));
+    }
+
+    /// Adds `token`, and any trivia before it. Placeholder tokens and eof are not part of the tree.
+    pub fn token(&mut self, token: Token<'a>) {
+        if matches!(token.kind, TokenKind::Dummy | TokenKind::Eof) {
+            return;
+        }
+
+        self.trivia(token.span.start());
+        self.offset = token.span.end();
+        self.children.push(SyntaxElement::Token(token));
+    }
+
+    /// Adds the rest of the source and returns the root of the tree.
+    pub fn finish(mut self) -> SyntaxNode<'a> {
+        while !self.parents.is_empty() {
+            self.finish_node();
+        }
+        self.trivia(self.source.len());
+
+        let children = std::mem::take(&mut self.children);
+        self.node(NodeKind::Root, children)
+    }
+
+    fn node(&self, kind: NodeKind, children: Vec<SyntaxElement<'a>>) -> SyntaxNode<'a> {
+        let span = match (children.first(), children.last()) {
+            (Some(first), Some(last)) => Span::between(first.span(), last.span()),
+            _ => Span::new(self.offset, self.offset),
+        };
+
+        SyntaxNode {
+            kind,
+            span,
+            children,
+        }
+    }
+
+    /// Adds the source from the end of the last token up to `end` as whitespace, comment and
+    /// unknown tokens. The lexer skipped all of it, so it contains no other tokens.
+    fn trivia(&mut self, end: usize) {
+        while self.offset < end {
+            let rest = &self.source[self.offset..end];
+            let whitespace = rest.len()
+                - rest
+                    .trim_start_matches(|c: char| c.is_ascii_whitespace())
+                    .len();
+            let (kind, len) = if rest.starts_with("//") {
+                let len = rest.find(['\n', '\0']).unwrap_or(rest.len());
+                (TokenKind::Comment, len)
+            } else if whitespace > 0 {
+                (TokenKind::Whitespace, whitespace)
+            } else {
+                let c = rest.chars().next().expect("rest is not empty");
+                (TokenKind::Unknown, c.len_utf8())
+            };
+
+            let span = Span::new(self.offset, self.offset + len);
+            self.children.push(SyntaxElement::Token(Token {
+                lexeme: span.slice(self.source),
+                span,
+                kind,
+            }));
+            self.offset = span.end();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{lexer::token::TokenKind, parser::Parser};
+
+    use super::{NodeKind, SyntaxElement};
+
+    #[test]
+    fn lossless() {
+        for source in [
+            "",
+            "  1 + (2*3) ; // comment\n\n-4;\t",
+            "1 +; (2; 3 # 4;\n)",
+            "é ( 1;\0 2;",
+            "// only a comment",
+            "sqrt(2) x 3;\nfoo",
+        ] {
+            assert_eq!(Parser::new(source).parse_syntax().to_string(), source);
+        }
+    }
+
+    #[test]
+    fn structure() {
+        let root = Parser::new("// c\n(1) + 2; 3 +;").parse_syntax();
+        let kinds: Vec<_> = root.nodes().map(|node| node.kind()).collect();
+        assert_eq!(kinds, [NodeKind::Stmt, NodeKind::Stmt]);
+        assert!(matches!(
+            &root.children()[0],
+            SyntaxElement::Token(token) if token.kind == TokenKind::Comment
+        ));
+
+        let stmt = root.nodes().next().unwrap();
+        let binary = stmt.nodes().next().unwrap();
+        assert_eq!(binary.kind(), NodeKind::Binary);
+        assert_eq!(binary.to_string(), "(1) + 2");
+        let paren = binary.nodes().next().unwrap();
+        assert_eq!(paren.kind(), NodeKind::Paren);
+        assert_eq!(paren.tokens().count(), 2);
+    }
+}
@@ -0,0 +1,106 @@
+//! Lowers a `SyntaxNode` to the declarations of an `Ast`, dropping trivia and parentheses.
+
+use crate::{
+    ast::{Decl, Expr, ExprKind, Stmt},
+    parser::{infix_op, prefix_op},
+};
+
+use super::{NodeKind, SyntaxNode};
+
+/// Lowers every statement in the tree rooted at `root`. Expressions that failed to parse or lower
+/// become `ExprKind::Error`, while tokens skipped between statements are left out.
+pub fn lower(root: &SyntaxNode) -> Vec<Decl> {
+    root.nodes()
+        .filter(|node| node.kind() == NodeKind::Stmt)
+        .map(|stmt| {
+            let expr = match stmt.nodes().next() {
+                Some(node) => lower_expr(node),
+                None => Expr::new(stmt.span(), ExprKind::Error),
+            };
+            Decl::stmt(Stmt::expr(expr))
+        })
+        .collect()
+}
+
+fn lower_expr(node: &SyntaxNode) -> Expr {
+    try_lower_expr(node).unwrap_or_else(|| Expr::new(node.span(), ExprKind::Error))
+}
+
+fn try_lower_expr(node: &SyntaxNode) -> Option<Expr> {
+    let expr = match node.kind() {
+        NodeKind::Number => {
+            let token = node.tokens().next()?;
+            Expr::new(token.span, ExprKind::Number(token.lexeme.parse().ok()?))
+        }
+        NodeKind::Paren => lower_expr(node.nodes().next()?),
+        NodeKind::Unary => {
+            let op = prefix_op(node.tokens().next()?)?;
+            Expr::unary(op, lower_expr(node.nodes().next()?))
+        }
+        NodeKind::Binary => {
+            let op = infix_op(node.tokens().next()?)?;
+            let mut operands = node.nodes();
+            let operand_1 = lower_expr(operands.next()?);
+            let operand_2 = lower_expr(operands.next()?);
+            Expr::binary(op, operand_1, operand_2)
+        }
+        NodeKind::Error => Expr::new(node.span(), ExprKind::Error),
+        NodeKind::Root | NodeKind::Stmt => return None,
+    };
+
+    Some(expr)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ast::{DeclKind, ExprKind, StmtKind},
+        lexer::{
+            span::Span,
+            token::{Token, TokenKind},
+        },
+        syntax::{Builder, NodeKind},
+    };
+
+    use super::lower;
+
+    #[test]
+    fn keeps_statements_that_fail_to_lower() {
+        // A binary node missing its right operand, which the parser never builds.
+        let source = "1 +; 2;";
+        let token = |start, end, kind| Token {
+            lexeme: &source[start..end],
+            span: Span::new(start, end),
+            kind,
+        };
+        let mut builder = Builder::new(source);
+        builder.start_node(Span::new(0, 1), NodeKind::Stmt);
+        builder.start_node(Span::new(0, 1), NodeKind::Binary);
+        builder.start_node(Span::new(0, 1), NodeKind::Number);
+        builder.token(token(0, 1, TokenKind::Number));
+        builder.finish_node();
+        builder.token(token(2, 3, TokenKind::Plus));
+        builder.finish_node();
+        builder.token(token(3, 4, TokenKind::Semicolon));
+        builder.finish_node();
+        builder.start_node(Span::new(5, 6), NodeKind::Stmt);
+        builder.start_node(Span::new(5, 6), NodeKind::Number);
+        builder.token(token(5, 6, TokenKind::Number));
+        builder.finish_node();
+        builder.token(token(6, 7, TokenKind::Semicolon));
+        let decls = lower(&builder.finish());
+
+        let exprs: Vec<_> = decls
+            .iter()
+            .map(|decl| match decl.kind() {
+                DeclKind::Stmt(stmt) => match stmt.kind() {
+                    StmtKind::Expr(expr) => expr,
+                },
+            })
+            .collect();
+        assert_eq!(exprs.len(), 2);
+        assert!(matches!(exprs[0].kind(), ExprKind::Error));
+        assert_eq!(exprs[0].span(), Span::new(0, 3));
+        assert!(matches!(exprs[1].kind(), ExprKind::Number(2.0)));
+    }
+}
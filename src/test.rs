@@ -0,0 +1,256 @@
+//! Property tests over the whole pipeline, run on randomly generated programs.
+//!
+//! Each program is checked to print to source that parses back to the same tree, and to produce
+//! bit-for-bit the same values as a direct evaluation of the tree on every backend, with and
+//! without optimization.
+
+use crate::{
+    ast::{Ast, BinOp, BinOpKind, Decl, DeclKind, Expr, ExprKind, Stmt, StmtKind, UnOp, UnOpKind},
+    codegen::CodeGenerator,
+    formatter::{format, MAX_WIDTH},
+    interpreter::interpret,
+    lexer::span::Span,
+    optimizer::{fold::fold_constants, peephole},
+    parser::Parser,
+    printer::print,
+    register,
+    vm::Vm,
+};
+
+const CASES: u64 = 2000;
+
+/// A xorshift pseudorandom number generator, so failures can be reproduced from the case number.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state must never be zero.
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+/// Generates a program of well-formed expression statements, using only numbers the parser could
+/// have produced. Spans are all empty, as there is no source.
+fn program(rng: &mut Rng) -> Ast {
+    let decls = (0..1 + rng.below(4))
+        .map(|_| Decl::stmt(Stmt::expr(expr(rng, 5))))
+        .collect();
+    Ast::new(decls, true)
+}
+
+fn expr(rng: &mut Rng, depth: u32) -> Expr {
+    let span = Span::new(0, 0);
+    if depth == 0 || rng.below(4) == 0 {
+        return Expr::new(span, ExprKind::Number(number(rng)));
+    }
+
+    if rng.below(5) == 0 {
+        Expr::unary(UnOp::new(span, UnOpKind::Neg), expr(rng, depth - 1))
+    } else {
+        let kind = [
+            BinOpKind::Add,
+            BinOpKind::Sub,
+            BinOpKind::Mul,
+            BinOpKind::Div,
+            BinOpKind::Rem,
+        ][rng.below(5) as usize];
+        Expr::binary(
+            BinOp::new(span, kind),
+            expr(rng, depth - 1),
+            expr(rng, depth - 1),
+        )
+    }
+}
+
+fn number(rng: &mut Rng) -> f64 {
+    match rng.below(4) {
+        0 => 0.0,
+        1 => rng.below(10) as f64,
+        2 => rng.below(1_000_000) as f64 / 1000.0,
+        _ => {
+            let value = f64::from_bits(rng.next()).abs() % 1e20;
+            if value.is_finite() {
+                value
+            } else {
+                1e20
+            }
+        }
+    }
+}
+
+/// The tree as an s-expression, ignoring spans, with numbers written as their bits.
+fn shape(expr: &Expr) -> String {
+    match expr.kind() {
+        ExprKind::Number(value) => format!("{:#x}", value.to_bits()),
+        ExprKind::Error => "error".to_owned(),
+        ExprKind::Unary(op, operand) => format!("({:?} {})", op.kind(), shape(operand)),
+        ExprKind::Binary(op, operand_1, operand_2) => format!(
+            "({:?} {} {})",
+            op.kind(),
+            shape(operand_1),
+            shape(operand_2)
+        ),
+    }
+}
+
+fn shapes(ast: &Ast) -> Vec<String> {
+    ast.decls()
+        .iter()
+        .map(|decl| match decl.kind() {
+            DeclKind::Stmt(stmt) => match stmt.kind() {
+                StmtKind::Expr(expr) => shape(expr),
+            },
+        })
+        .collect()
+}
+
+/// Evaluates the tree with Rust's own operators, independently of the rest of the crate.
+fn reference(expr: &Expr) -> f64 {
+    match expr.kind() {
+        ExprKind::Number(value) => *value,
+        ExprKind::Error => f64::NAN,
+        ExprKind::Unary(_, operand) => -reference(operand),
+        ExprKind::Binary(op, operand_1, operand_2) => {
+            let (a, b) = (reference(operand_1), reference(operand_2));
+            match op.kind() {
+                BinOpKind::Add => a + b,
+                BinOpKind::Sub => a - b,
+                BinOpKind::Mul => a * b,
+                BinOpKind::Div => a / b,
+                BinOpKind::Rem => a % b,
+            }
+        }
+    }
+}
+
+fn bits(values: &[f64]) -> Vec<u64> {
+    values.iter().map(|value| value.to_bits()).collect()
+}
+
+#[test]
+fn print_parse_round_trip() {
+    for case in 0..CASES {
+        let ast = program(&mut Rng::new(case));
+        let source = print(&ast);
+        let parsed = Parser::new(&source).parse();
+        assert!(parsed.complete(), "case {}: {}", case, source);
+        assert_eq!(shapes(&parsed), shapes(&ast), "case {}: {}", case, source);
+        let tree = Parser::new(&source).parse_syntax();
+        assert_eq!(tree.to_string(), source, "case {}", case);
+    }
+}
+
+#[test]
+fn format_round_trip() {
+    for case in 0..CASES {
+        let ast = program(&mut Rng::new(case));
+        let source = print(&ast);
+        let formatted = format(&ast, &source);
+        let parsed = Parser::new(&formatted).parse();
+        assert!(parsed.complete(), "case {}: {}", case, formatted);
+        assert_eq!(
+            shapes(&parsed),
+            shapes(&ast),
+            "case {}: {}",
+            case,
+            formatted
+        );
+        assert_eq!(format(&parsed, &formatted), formatted, "case {}", case);
+
+        // Only numbers too long to fit may overflow a line.
+        for line in formatted.lines() {
+            assert!(
+                line.len() <= MAX_WIDTH || line.split(' ').any(|word| word.len() > 20),
+                "case {}: {}",
+                case,
+                formatted
+            );
+        }
+    }
+}
+
+#[test]
+fn backends_match_reference() {
+    for case in 0..CASES {
+        let ast = program(&mut Rng::new(case));
+        let expected: Vec<_> = ast
+            .decls()
+            .iter()
+            .map(|decl| match decl.kind() {
+                DeclKind::Stmt(stmt) => match stmt.kind() {
+                    StmtKind::Expr(expr) => reference(expr),
+                },
+            })
+            .collect();
+
+        let bytecode = CodeGenerator::default().generate(&ast).unwrap();
+        let vm = Vm::new(bytecode).run().unwrap();
+        let program = register::codegen::CodeGenerator::default()
+            .generate(&ast)
+            .unwrap();
+        let register = register::vm::Vm::new(program).run().unwrap();
+
+        let source = print(&ast);
+        assert_eq!(bits(&vm), bits(&expected), "case {}: {}", case, source);
+        assert_eq!(
+            bits(&register),
+            bits(&expected),
+            "case {}: {}",
+            case,
+            source
+        );
+        assert_eq!(
+            bits(&interpret(&ast)),
+            bits(&expected),
+            "case {}: {}",
+            case,
+            source
+        );
+    }
+}
+
+#[test]
+fn optimization_preserves_results() {
+    for case in 0..CASES {
+        let ast = program(&mut Rng::new(case));
+        let unoptimized = CodeGenerator::default().generate(&ast).unwrap();
+        let expected = Vm::new(unoptimized.clone()).run().unwrap();
+
+        let folded = CodeGenerator::default()
+            .generate(&fold_constants(&ast))
+            .unwrap();
+        let peepholed = peephole::optimize(&unoptimized);
+        let both = peephole::optimize(&folded);
+
+        let source = print(&ast);
+        for bytecode in [folded, peepholed, both] {
+            let values = Vm::new(bytecode).run().unwrap();
+            assert_eq!(bits(&values), bits(&expected), "case {}: {}", case, source);
+        }
+
+        // Folded trees contain numbers the parser can't produce, which must still print to
+        // source evaluating to the same values. NaN payloads aren't preserved by printing.
+        let reprinted = Parser::new(&print(&fold_constants(&ast))).parse();
+        let values = interpret(&reprinted);
+        assert_eq!(values.len(), expected.len());
+        for (value, expected) in values.iter().zip(&expected) {
+            assert!(
+                value.to_bits() == expected.to_bits() || value.is_nan() && expected.is_nan(),
+                "case {}: {}",
+                case,
+                source
+            );
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt::Display;
+
+use crate::bytecode::{Bytecode, DecodeError};
+
+/// Checks that `bytecode` can be run by the `Vm` without it encountering malformed code.
+///
+/// Every byte must belong to a complete instruction with a valid opcode, every constant operand
+/// must refer to an entry in the constant table, and no instruction may pop more values than are
+/// on the stack, or push more than its declared maximum stack depth. There are no branching
+/// instructions, so the code is checked as the single path running from the first instruction to
+/// the last.
+pub fn verify(bytecode: &Bytecode) -> Result<(), VerifyError> {
+    analyze(bytecode, Some(bytecode.max_stack_depth())).map(|_| ())
+}
+
+/// Computes the greatest number of values on the stack at any point while running `bytecode`,
+/// performing the same checks as [`verify`] except for the declared maximum stack depth.
+pub fn max_stack_depth(bytecode: &Bytecode) -> Result<usize, VerifyError> {
+    analyze(bytecode, None)
+}
+
+fn analyze(bytecode: &Bytecode, depth_limit: Option<usize>) -> Result<usize, VerifyError> {
+    let code = bytecode.as_ref();
+    let mut depth = 0;
+    let mut max_depth = 0;
+    let mut offset = 0;
+    while offset < code.len() {
+        let error = |kind| VerifyError { offset, kind };
+
+        let instruction = bytecode.decode(offset).map_err(|err| {
+            error(match err {
+                DecodeError::InvalidOpcode(byte) => VerifyErrorKind::InvalidOpcode(byte),
+                DecodeError::UnexpectedEnd => VerifyErrorKind::TruncatedInstruction,
+            })
+        })?;
+
+        if let Some(idx) = instruction.operand {
+            if idx as usize >= bytecode.constants().len() {
+                return Err(error(VerifyErrorKind::ConstantOutOfRange(idx)));
+            }
+        }
+
+        let (pops, pushes) = instruction.opcode.stack_effect();
+        depth = usize::checked_sub(depth, pops)
+            .ok_or_else(|| error(VerifyErrorKind::StackUnderflow))?
+            + pushes;
+        if depth_limit.is_some_and(|limit| depth > limit) {
+            return Err(error(VerifyErrorKind::StackDepthExceeded));
+        }
+        max_depth = max_depth.max(depth);
+
+        offset += instruction.size();
+    }
+
+    Ok(max_depth)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyError {
+    /// Offset of the first byte of the offending instruction.
+    pub offset: usize,
+    pub kind: VerifyErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    InvalidOpcode(u8),
+    /// The code ends before all of the instruction's operands.
+    TruncatedInstruction,
+    ConstantOutOfRange(u8),
+    /// The instruction pops more values than the stack holds.
+    StackUnderflow,
+    /// The instruction pushes more values than the declared maximum stack depth allows for.
+    StackDepthExceeded,
+}
+
+impl VerifyError {
+    /// The stable code identifying the error, which `calculator --explain` describes.
+    pub fn code(&self) -> &'static str {
+        "E0401"
+    }
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at offset {:04}: ", self.offset)?;
+        match self.kind {
+            VerifyErrorKind::InvalidOpcode(byte) => write!(f, "invalid opcode 0x{:02X}", byte),
+            VerifyErrorKind::TruncatedInstruction => write!(f, "truncated instruction"),
+            VerifyErrorKind::ConstantOutOfRange(idx) => write!(f, "no constant with index {}", idx),
+            VerifyErrorKind::StackUnderflow => write!(f, "stack underflow"),
+            VerifyErrorKind::StackDepthExceeded => write!(f, "stack exceeds declared depth"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assembler::assemble, codegen::CodeGenerator, parser::Parser};
+
+    use super::{verify, VerifyError, VerifyErrorKind};
+
+    fn verify_asm(source: &str) -> Result<(), VerifyError> {
+        verify(&assemble(source).unwrap())
+    }
+
+    #[test]
+    fn generated_code() -> Result<(), VerifyError> {
+        let ast = Parser::new("-(1 + 2) * 3.5; 4 % 0;").parse();
+        verify(&CodeGenerator::default().generate(&ast).unwrap())
+    }
+
+    #[test]
+    fn malformed_code() {
+        let kind = |source| verify_asm(source).unwrap_err().kind;
+        assert_eq!(
+            kind("Constant 1\n.byte 0xFF"),
+            VerifyErrorKind::InvalidOpcode(0xFF)
+        );
+        assert_eq!(
+            kind("Constant 1\n.byte 0"),
+            VerifyErrorKind::TruncatedInstruction
+        );
+        assert_eq!(
+            kind("Constant 1\nConstant #1"),
+            VerifyErrorKind::ConstantOutOfRange(1)
+        );
+
+        let err = verify_asm("Constant 1\nConstant 2\nAdd\nAdd").unwrap_err();
+        assert_eq!(err.kind, VerifyErrorKind::StackUnderflow);
+        assert_eq!(err.offset, 5);
+    }
+
+    #[test]
+    fn declared_stack_depth() {
+        let mut bytecode = assemble("Constant 1\nConstant 2\nAdd\nEmit").unwrap();
+        assert_eq!(bytecode.max_stack_depth(), 2);
+
+        bytecode.set_max_stack_depth(1);
+        let err = verify(&bytecode).unwrap_err();
+        assert_eq!(err.kind, VerifyErrorKind::StackDepthExceeded);
+        assert_eq!(err.offset, 2);
+    }
+}
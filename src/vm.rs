@@ -1,25 +1,125 @@
-use crate::bytecode::{Bytecode, Opcode};
-use std::convert::TryInto;
+use crate::{
+    bytecode::{Bytecode, Opcode},
+    diagnostics::{Diagnostic, Label},
+    lexer::span::Span,
+};
+use std::{convert::TryInto, time::Instant};
 
 pub struct Vm {
     bytecode: Bytecode,
     ip: usize,
     stack: Vec<f64>,
+    /// The greatest number of values the stack may hold.
+    stack_limit: usize,
+    /// Values emitted by the program that have not yet been returned from `run`.
+    values: Vec<f64>,
+    /// The number of instructions left to execute, or `None` if there is no limit.
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    /// The number of instructions executed, used to decide when to check the deadline.
+    executed: u64,
+    /// The offset of the instruction being executed.
+    instruction_start: usize,
+    /// The greatest number of bytes the program may hold at once, or `None` if there is no limit.
+    memory_limit: Option<usize>,
 }
 
 impl Vm {
+    /// The default for the greatest number of values the stack may hold.
+    pub const DEFAULT_STACK_LIMIT: usize = 1 << 16;
+
+    /// Reading the clock is slow relative to executing an instruction, so the deadline is only
+    /// checked once every this many instructions.
+    pub(crate) const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
     pub fn new(bytecode: Bytecode) -> Self {
-        Self {
+        let mut vm = Self {
+            stack: vec![],
             bytecode,
             ip: 0,
-            stack: vec![],
+            stack_limit: Self::DEFAULT_STACK_LIMIT,
+            values: vec![],
+            fuel: None,
+            deadline: None,
+            executed: 0,
+            instruction_start: 0,
+            memory_limit: None,
+        };
+        vm.allocate_stack();
+        vm
+    }
+
+    /// Preallocates the stack for as many values as the program declares it needs, but no more
+    /// than the stack limit or the memory limit allow.
+    fn allocate_stack(&mut self) {
+        let mut capacity = self.bytecode.max_stack_depth().min(self.stack_limit);
+        if let Some(bytes) = self.memory_limit {
+            capacity = capacity.min(bytes / size_of::<f64>());
         }
+        self.stack = Vec::with_capacity(capacity);
+    }
+
+    /// Sets the greatest number of values the stack may hold, beyond which running the program
+    /// fails with [`VmError::StackOverflow`].
+    pub fn with_stack_limit(mut self, limit: usize) -> Self {
+        self.stack_limit = limit;
+        self.allocate_stack();
+        self
+    }
+
+    /// Limits the number of bytes of values the program may hold at once, counting both its
+    /// stack and the values it has emitted, beyond which running the program fails with
+    /// [`VmError::OutOfMemory`].
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self.allocate_stack();
+        self
+    }
+
+    /// The number of bytes of values the program currently holds.
+    pub fn allocated(&self) -> usize {
+        (self.stack.len() + self.values.len()) * size_of::<f64>()
+    }
+
+    /// Limits the number of instructions `run` may execute before failing with
+    /// [`VmError::BudgetExhausted`].
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Sets a time after which `run` fails with [`VmError::BudgetExhausted`].
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Allows `fuel` more instructions to be executed, so that a program which exhausted its
+    /// budget can be resumed by calling `run` again.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        self.fuel = self.fuel.map(|current| current.saturating_add(fuel));
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
     }
 
-    pub fn run(&mut self) -> Result<f64, VmError> {
-        let value = loop {
+    /// Runs the program until it returns or reaches the end of its code, yielding the values of
+    /// its top-level expression statements in the order they were evaluated.
+    ///
+    /// If the budget is exhausted the program is suspended before the next instruction, and
+    /// calling `run` again after adding fuel or extending the deadline resumes it. Values emitted
+    /// before the suspension are yielded when it eventually finishes.
+    pub fn run(&mut self) -> Result<Vec<f64>, VmError> {
+        while !self.is_at_end() {
+            self.consume_budget()?;
+            self.instruction_start = self.ip;
             match self
-                .read_byte()
+                .read_byte()?
                 .try_into()
                 .map_err(|_| VmError::InvalidOpcode)?
             {
@@ -53,30 +153,99 @@ impl Vm {
                     self.push(-a)?;
                 }
                 Opcode::Constant => {
-                    let offset = self.read_byte();
-                    let value = self.bytecode.constant(offset);
+                    let value = self.read_constant()?;
                     self.push(value)?;
                 }
+                Opcode::AddConstant => {
+                    let b = self.read_constant()?;
+                    let a = self.pop()?;
+                    self.push(a + b)?;
+                }
+                Opcode::SubtractConstant => {
+                    let b = self.read_constant()?;
+                    let a = self.pop()?;
+                    self.push(a - b)?;
+                }
+                Opcode::MultiplyConstant => {
+                    let b = self.read_constant()?;
+                    let a = self.pop()?;
+                    self.push(a * b)?;
+                }
+                Opcode::DivideConstant => {
+                    let b = self.read_constant()?;
+                    let a = self.pop()?;
+                    self.push(a / b)?;
+                }
                 Opcode::Pop => {
                     self.pop()?;
                 }
+                Opcode::Emit => {
+                    let value = self.pop()?;
+                    self.values.push(value);
+                }
                 Opcode::Return => {
-                    break self.pop()?;
+                    break;
                 }
             }
-        };
+        }
+
+        Ok(std::mem::take(&mut self.values))
+    }
+
+    fn consume_budget(&mut self) -> Result<(), VmError> {
+        let out_of_fuel = self.fuel == Some(0);
+        let past_deadline = self.deadline.is_some_and(|deadline| {
+            self.executed.is_multiple_of(Self::DEADLINE_CHECK_INTERVAL)
+                && Instant::now() >= deadline
+        });
+        if out_of_fuel || past_deadline {
+            return Err(VmError::BudgetExhausted {
+                span: self.bytecode.span(self.ip),
+            });
+        }
+
+        if let Some(fuel) = &mut self.fuel {
+            *fuel -= 1;
+        }
+        self.executed += 1;
+
+        Ok(())
+    }
 
-        Ok(value)
+    fn is_at_end(&self) -> bool {
+        self.ip >= self.bytecode.as_ref().len()
     }
 
-    fn read_byte(&mut self) -> u8 {
-        let slice = self.bytecode.as_ref();
-        let byte = slice[self.ip];
+    fn read_byte(&mut self) -> Result<u8, VmError> {
+        let byte = *self
+            .bytecode
+            .as_ref()
+            .get(self.ip)
+            .ok_or(VmError::UnexpectedEnd)?;
         self.ip += 1;
-        byte
+        Ok(byte)
+    }
+
+    fn read_constant(&mut self) -> Result<f64, VmError> {
+        let idx = self.read_byte()?;
+        self.bytecode
+            .constants()
+            .get(idx as usize)
+            .copied()
+            .ok_or(VmError::ConstantOutOfRange(idx))
     }
 
     fn push(&mut self, value: f64) -> Result<(), VmError> {
+        if self.stack.len() >= self.stack_limit {
+            return Err(VmError::StackOverflow);
+        }
+        if let Some(limit) = self.memory_limit {
+            if self.allocated() + size_of::<f64>() > limit {
+                return Err(VmError::OutOfMemory {
+                    span: self.bytecode.span(self.instruction_start),
+                });
+            }
+        }
         self.stack.push(value);
 
         Ok(())
@@ -91,4 +260,177 @@ impl Vm {
 pub enum VmError {
     MissingOperand,
     InvalidOpcode,
+    /// The code ended in the middle of an instruction.
+    UnexpectedEnd,
+    /// An instruction referred to a constant that does not exist.
+    ConstantOutOfRange(u8),
+    /// A value was pushed onto a stack already holding as many values as its limit.
+    StackOverflow,
+    /// The program ran out of fuel or passed its deadline before the instruction generated from
+    /// `span` could be executed.
+    BudgetExhausted {
+        span: Option<Span>,
+    },
+    /// The instruction generated from `span` would have exceeded the memory limit.
+    OutOfMemory {
+        span: Option<Span>,
+    },
+}
+
+impl VmError {
+    pub fn message(&self) -> String {
+        match self {
+            VmError::MissingOperand => "instruction popped an empty stack".to_owned(),
+            VmError::InvalidOpcode => "invalid opcode".to_owned(),
+            VmError::UnexpectedEnd => "code ended in the middle of an instruction".to_owned(),
+            VmError::ConstantOutOfRange(idx) => format!("constant {} does not exist", idx),
+            VmError::StackOverflow => "stack overflow".to_owned(),
+            VmError::BudgetExhausted { .. } => "execution budget exhausted".to_owned(),
+            VmError::OutOfMemory { .. } => "out of memory".to_owned(),
+        }
+    }
+
+    /// The stable code identifying the error, which `calculator --explain` describes.
+    pub fn code(&self) -> &'static str {
+        match self {
+            VmError::MissingOperand => "E0200",
+            VmError::InvalidOpcode => "E0201",
+            VmError::UnexpectedEnd => "E0202",
+            VmError::ConstantOutOfRange(_) => "E0203",
+            VmError::StackOverflow => "E0204",
+            VmError::BudgetExhausted { .. } => "E0205",
+            VmError::OutOfMemory { .. } => "E0206",
+        }
+    }
+
+    /// The span of the source the failing instruction was generated from, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            VmError::BudgetExhausted { span } | VmError::OutOfMemory { span } => *span,
+            _ => None,
+        }
+    }
+
+    /// Returns the error as a diagnostic, pointing at the source of the failing instruction if
+    /// it is known.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.message()).with_code(self.code());
+        match self.span() {
+            Some(span) => diagnostic.with_label(Label::primary(span, "while evaluating this")),
+            None => diagnostic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        bytecode::{Bytecode, Opcode},
+        codegen::CodeGenerator,
+        lexer::span::Span,
+        parser::Parser,
+    };
+
+    use super::{Vm, VmError};
+
+    fn eval(source: &str) -> Result<Vec<f64>, VmError> {
+        let ast = Parser::new(source).parse();
+        assert!(ast.complete());
+        Vm::new(CodeGenerator::default().generate(&ast).unwrap()).run()
+    }
+
+    #[test]
+    fn emits_every_statement() -> Result<(), VmError> {
+        assert_eq!(eval("1 + 1; 2 * 3;")?, vec![2.0, 6.0]);
+        assert_eq!(eval("")?, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stack_limit() {
+        let ast = Parser::new("1 + (2 + (3 + 4));").parse();
+        let bytecode = CodeGenerator::default().generate(&ast).unwrap();
+        assert_eq!(bytecode.max_stack_depth(), 4);
+        assert_eq!(
+            Vm::new(bytecode.clone()).with_stack_limit(4).run().unwrap(),
+            vec![10.0]
+        );
+        assert!(matches!(
+            Vm::new(bytecode).with_stack_limit(3).run(),
+            Err(VmError::StackOverflow)
+        ));
+    }
+
+    #[test]
+    fn resume_with_fuel() {
+        let ast = Parser::new("1 + 2; 3 * 4;").parse();
+        let mut vm = Vm::new(CodeGenerator::default().generate(&ast).unwrap()).with_fuel(5);
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::BudgetExhausted { span: Some(span) }) if span == Span::new(11, 12)
+        ));
+
+        vm.add_fuel(1);
+        assert!(vm.run().is_err());
+
+        vm.add_fuel(2);
+        assert_eq!(vm.run().unwrap(), vec![3.0, 12.0]);
+        assert_eq!(vm.fuel(), Some(0));
+    }
+
+    #[test]
+    fn memory_limit() {
+        let run = |source, limit| {
+            let ast = Parser::new(source).parse();
+            let bytecode = CodeGenerator::default().generate(&ast).unwrap();
+            Vm::new(bytecode).with_memory_limit(limit).run()
+        };
+
+        assert_eq!(run("1 + 2;", 16).unwrap(), vec![3.0]);
+        assert!(matches!(
+            run("1 + (2 + 3);", 16),
+            Err(VmError::OutOfMemory { span: Some(span) }) if span == Span::new(9, 10)
+        ));
+
+        // Emitted values are held until the program finishes.
+        assert!(matches!(
+            run("1; 2; 3;", 16),
+            Err(VmError::OutOfMemory { .. })
+        ));
+
+        // The stack is never preallocated beyond the memory limit, whichever is set last.
+        let ast = Parser::new("1 + (2 + (3 + 4));").parse();
+        let bytecode = CodeGenerator::default().generate(&ast).unwrap();
+        let vm = Vm::new(bytecode.clone())
+            .with_memory_limit(16)
+            .with_stack_limit(8);
+        assert_eq!(vm.stack.capacity(), 2);
+        let vm = Vm::new(bytecode)
+            .with_stack_limit(3)
+            .with_memory_limit(1024);
+        assert_eq!(vm.stack.capacity(), 3);
+    }
+
+    #[test]
+    fn truncated_operand() {
+        let mut bytecode = Bytecode::default();
+        bytecode.add_constant(1.0);
+        bytecode.write_opcode(Opcode::Constant, Span::new(0, 1));
+        assert!(matches!(
+            Vm::new(bytecode).run(),
+            Err(VmError::UnexpectedEnd)
+        ));
+    }
+
+    #[test]
+    fn constant_out_of_range() {
+        let mut bytecode = Bytecode::default();
+        bytecode.write_opcode(Opcode::Constant, Span::new(0, 1));
+        bytecode.write_byte(3, Span::new(0, 1));
+        assert!(matches!(
+            Vm::new(bytecode).run(),
+            Err(VmError::ConstantOutOfRange(3))
+        ));
+    }
 }